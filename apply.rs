@@ -0,0 +1,358 @@
+use crate::xrandr::ScreenLimits;
+use crate::{gamma, icc, Monitor};
+use std::process::Command;
+
+// Every argument xrandr needs to put each output in its proposed state.
+// Shared by the Apply button and the command line so they can't drift apart.
+// `framebuffer` is a screen size picked by hand, otherwise xrandr works it out from the outputs
+pub fn xrandr_args(monitors: &[Monitor], framebuffer: Option<(u32, u32)>) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some((width, height)) = framebuffer {
+        args.push(String::from("--fb"));
+        args.push(format!("{}x{}", width, height));
+    }
+    for monitor in monitors {
+        args.push(String::from("--output"));
+        args.push(monitor.id.clone());
+        if monitor.proposed_status {
+            match monitor.proposed_resolution {
+                Some((width, height)) => {
+                    // Custom modes can only be picked by their full name
+                    let named = monitor.mode_names.iter().find(|(size, rate, _)| {
+                        *size == (width, height) && monitor.proposed_rate.map_or(false, |proposed| (proposed - rate).abs() < 0.01)
+                    });
+                    args.push(String::from("--mode"));
+                    args.push(match named {
+                        Some((_, _, name)) => name.clone(),
+                        None => format!("{}x{}", width, height),
+                    });
+                    if let Some(rate) = monitor.proposed_rate {
+                        args.push(String::from("--rate"));
+                        args.push(format!("{:.2}", rate)); // xrandr picks the closest rate so rounding is fine
+                    }
+                }
+                None => args.push(String::from("--auto")), // Just turned on and nobody picked a mode, use the preferred one
+            }
+            args.push(String::from("--pos"));
+            args.push(format!("{}x{}", monitor.proposed_position.0, monitor.proposed_position.1));
+            args.push(String::from("--rotate"));
+            args.push(monitor.proposed_orientation.clone());
+            if monitor.proposed_primary {
+                args.push(String::from("--primary"));
+            }
+            // Also when it's being turned off, 0x0 is how panning goes away
+            if monitor.proposed_panning.enabled() || monitor.panning.enabled() {
+                args.push(String::from("--panning"));
+                args.push(monitor.proposed_panning.arg(monitor.proposed_position));
+            }
+            // Left out when nothing ever touched them so a plain apply doesn't rewrite the gamma ramps
+            let neutral = (1.0, [1.0; 3]);
+            if (monitor.proposed_brightness, monitor.proposed_gamma) != neutral || (monitor.brightness, monitor.gamma) != neutral {
+                args.extend(color_args(monitor));
+            }
+        } else {
+            args.push(String::from("--off"));
+        }
+        // Properties work whether the output is on or not. Only the edited ones so nothing else gets poked
+        for (current, proposed) in monitor.properties.iter().zip(&monitor.proposed_properties) {
+            if current.value != proposed.value {
+                args.push(String::from("--set"));
+                args.push(proposed.name.clone());
+                args.push(proposed.value.clone());
+            }
+        }
+    }
+    if !monitors.iter().any(|monitor| monitor.proposed_status && monitor.proposed_primary) {
+        args.push(String::from("--noprimary")); // Otherwise unticking primary does nothing
+    }
+    args
+}
+
+// --brightness and --gamma for the proposed values
+fn color_args(monitor: &Monitor) -> Vec<String> {
+    let [red, green, blue] = monitor.proposed_gamma;
+    vec![
+        String::from("--brightness"),
+        format!("{:.2}", monitor.proposed_brightness),
+        String::from("--gamma"),
+        format!("{:.2}:{:.2}:{:.2}", red, green, blue),
+    ]
+}
+
+// Put the proposed brightness and gamma on screen straight away while the sliders move. Apply is still what keeps them.
+// With night light on or a calibration loaded the ramps get made here so those stay on while previewing
+pub fn preview_color(monitor: &mut Monitor, night_light: Option<u32>) -> Result<(), String> {
+    monitor.shown_color = (monitor.proposed_brightness, monitor.proposed_gamma);
+    if night_light.is_some() || monitor.calibration().is_some() {
+        return gamma::set_ramps(std::slice::from_ref(monitor), night_light);
+    }
+    let mut args = vec![String::from("--output"), monitor.id.clone()];
+    args.extend(color_args(monitor));
+    run_xrandr(&args)
+}
+
+// Run the whole layout as one xrandr call. On success the proposed settings become the current ones.
+// `screen` is what xrandr said the X screen can be, a layout that doesn't fit gets turned down before anything runs.
+// `night_light` is the tint to put back afterwards, None for untinted
+pub fn apply_monitors(
+    monitors: &mut [Monitor],
+    framebuffer: Option<(u32, u32)>,
+    screen: Option<&ScreenLimits>,
+    night_light: Option<u32>,
+) -> Result<(), String> {
+    normalize_positions(monitors);
+    check_screen_size(monitors, framebuffer, screen)?;
+    add_new_modes(monitors)?;
+    run_xrandr(&xrandr_args(monitors, framebuffer))?;
+
+    for monitor in monitors.iter_mut() {
+        monitor.enabled = monitor.proposed_status;
+        if monitor.proposed_status {
+            if let Some(resolution) = monitor.proposed_resolution {
+                monitor.resolution = resolution;
+            }
+            monitor.rate = monitor.proposed_rate;
+            monitor.current_position = monitor.proposed_position;
+            monitor.orientation = monitor.proposed_orientation.clone();
+            monitor.brightness = monitor.proposed_brightness;
+            monitor.gamma = monitor.proposed_gamma;
+            monitor.shown_color = (monitor.brightness, monitor.gamma);
+            monitor.panning = monitor.proposed_panning;
+        }
+        monitor.primary = monitor.proposed_primary;
+        monitor.properties = monitor.proposed_properties.clone();
+    }
+    apply_virtual_monitors(monitors)?;
+    apply_color(monitors, night_light)
+        .map_err(|e| format!("Layout applied but the color settings didn't go back on. {}", e))
+}
+
+// Night light tint, calibration curves and ICC atoms on top of whatever xrandr did. xrandr rewrites the ramps and some
+// drivers reset them on a mode change, so this runs after every Apply and when something gets plugged in
+pub fn apply_color(monitors: &[Monitor], night_light: Option<u32>) -> Result<(), String> {
+    if night_light.is_some() || monitors.iter().any(|monitor| monitor.calibration().is_some()) {
+        gamma::set_ramps(monitors, night_light)?;
+    }
+    icc::set_atoms(monitors)
+}
+
+// Virtual monitors are absolute on the X screen so they get set again every time in case their output moved.
+// --setmonitor replaces one with the same name, ones that went away get --delmonitor
+fn apply_virtual_monitors(monitors: &mut [Monitor]) -> Result<(), String> {
+    for monitor in monitors.iter_mut() {
+        let keep: &[_] = if monitor.enabled { &monitor.proposed_virtual } else { &[] };
+        for old in &monitor.virtual_monitors {
+            if !keep.iter().any(|region| region.name == old.name) {
+                run_xrandr(&[String::from("--delmonitor"), old.name.clone()])?;
+            }
+        }
+        for region in keep {
+            run_xrandr(&region.setmonitor_args(monitor.current_position, &monitor.id))?;
+        }
+        monitor.virtual_monitors = keep.to_vec();
+        monitor.proposed_virtual = monitor.virtual_monitors.clone(); // Off outputs lose theirs
+    }
+    Ok(())
+}
+
+// X wants the screen to start at 0,0 so shift everything if something got placed left of or above it
+fn normalize_positions(monitors: &mut [Monitor]) {
+    let enabled = monitors.iter().filter(|monitor| monitor.proposed_status);
+    let min_x = enabled.clone().map(|monitor| monitor.proposed_position.0).min().unwrap_or(0);
+    let min_y = enabled.map(|monitor| monitor.proposed_position.1).min().unwrap_or(0);
+    for monitor in monitors.iter_mut() {
+        monitor.proposed_position.0 -= min_x;
+        monitor.proposed_position.1 -= min_y;
+        // The tracking area is absolute too
+        let tracking = &mut monitor.proposed_panning.tracking;
+        if !tracking.is_empty() {
+            tracking.x -= min_x;
+            tracking.y -= min_y;
+        }
+    }
+}
+
+// Bottom-right corner of each output that'll be on, panning areas included. Measured from the top-left output since
+// normalize_positions puts that at 0,0
+fn extents(monitors: &[Monitor]) -> Vec<(&Monitor, (u32, u32))> {
+    let enabled = monitors.iter().filter(|monitor| monitor.proposed_status);
+    let min_x = enabled.clone().map(|monitor| monitor.proposed_position.0).min().unwrap_or(0);
+    let min_y = enabled.clone().map(|monitor| monitor.proposed_position.1).min().unwrap_or(0);
+    enabled
+        .map(|monitor| {
+            let (mut width, mut height) = monitor.screen_size();
+            if monitor.proposed_panning.enabled() {
+                width = width.max(monitor.proposed_panning.size.0);
+                height = height.max(monitor.proposed_panning.size.1);
+            }
+            let x = (monitor.proposed_position.0 - min_x) as u32;
+            let y = (monitor.proposed_position.1 - min_y) as u32;
+            (monitor, (x + width, y + height))
+        })
+        .collect()
+}
+
+// Screen size the proposed layout needs, what xrandr picks when there's no --fb
+pub fn framebuffer_size(monitors: &[Monitor]) -> (u32, u32) {
+    extents(monitors)
+        .iter()
+        .fold((0, 0), |(width, height), (_, (right, bottom))| (width.max(*right), height.max(*bottom)))
+}
+
+// Whether the X screen can be made the size this layout needs. The GPU can't allocate anything past the maximum on
+// xrandr's Screen line, so say which output goes past it and by how much instead of letting xrandr fail
+pub fn check_screen_size(monitors: &[Monitor], framebuffer: Option<(u32, u32)>, screen: Option<&ScreenLimits>) -> Result<(), String> {
+    let needed = framebuffer_size(monitors);
+    if let Some((width, height)) = framebuffer {
+        if width < needed.0 || height < needed.1 {
+            return Err(format!("A {}x{} screen is too small, the layout needs at least {}x{}", width, height, needed.0, needed.1));
+        }
+    }
+    let screen = match screen {
+        Some(screen) => screen,
+        None => return Ok(()), // Nothing to go by, let xrandr decide
+    };
+    let (width, height) = framebuffer.unwrap_or(needed);
+    let (max_width, max_height) = screen.maximum;
+    if width <= max_width && height <= max_height {
+        let (min_width, min_height) = screen.minimum;
+        if framebuffer.is_some() && (width < min_width || height < min_height) {
+            return Err(format!("The X screen can't be smaller than {}x{}", min_width, min_height));
+        }
+        return Ok(());
+    }
+    // The one sticking out furthest is the one to move
+    let furthest = extents(monitors)
+        .into_iter()
+        .filter(|(_, (right, bottom))| *right > max_width || *bottom > max_height)
+        .max_by_key(|(_, (right, bottom))| right.saturating_sub(max_width).max(bottom.saturating_sub(max_height)));
+    match furthest {
+        Some((monitor, (right, bottom))) => Err(format!(
+            "{} reaches {}x{} but the X screen can't be bigger than {}x{}. Move it closer to the others, give it a smaller mode or turn panning down",
+            monitor.id, right, bottom, max_width, max_height
+        )),
+        None => Err(format!("A {}x{} screen is bigger than the {}x{} the X screen can be", width, height, max_width, max_height)),
+    }
+}
+
+// Switch off outputs that aren't part of the layout, like disconnected ones still holding a CRTC.
+// Runs before the layout so it gets their CRTCs and the screen can shrink
+pub fn turn_off(outputs: &[String]) -> Result<(), String> {
+    if outputs.is_empty() {
+        return Ok(());
+    }
+    let mut args = Vec::new();
+    for id in outputs {
+        args.extend([String::from("--output"), id.clone(), String::from("--off")]);
+    }
+    run_xrandr(&args)
+}
+
+// One xrandr call. Whatever it complains about on stderr becomes the error
+pub fn run_xrandr(args: &[String]) -> Result<(), String> {
+    let output = Command::new("xrandr")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run xrandr: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "xrandr {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// Custom modes have to exist on the X server and be attached to the output before --mode can pick them
+fn add_new_modes(monitors: &mut [Monitor]) -> Result<(), String> {
+    for monitor in monitors.iter_mut() {
+        for modeline in &monitor.new_modes {
+            let mut newmode = vec![String::from("--newmode")];
+            newmode.extend(modeline.newmode_args());
+            // BadName is X saying a mode with this name exists, from an earlier try or another output. The Custom mode
+            // window made sure its timings match so --addmode can use it. Anything else is a real failure
+            if let Err(e) = run_xrandr(&newmode) {
+                if !e.contains("BadName") {
+                    return Err(e);
+                }
+            }
+            run_xrandr(&[String::from("--addmode"), monitor.id.clone(), modeline.name.clone()])?;
+        }
+        monitor.new_modes.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(id: &str, resolution: (u32, u32), position: (i32, i32)) -> Monitor {
+        Monitor {
+            id: id.to_string(),
+            proposed_status: true,
+            proposed_resolution: Some(resolution),
+            proposed_position: position,
+            ..Default::default()
+        }
+    }
+
+    fn screen(maximum: (u32, u32)) -> ScreenLimits {
+        ScreenLimits { minimum: (320, 200), current: (1920, 1080), maximum }
+    }
+
+    // Two 4k side by side, the second one turned on its side
+    fn layout() -> Vec<Monitor> {
+        let mut monitors = vec![monitor("DP-1", (3840, 2160), (0, 0)), monitor("DP-2", (3840, 2160), (3840, 0))];
+        monitors[1].proposed_orientation = String::from("left");
+        monitors
+    }
+
+    #[test]
+    fn framebuffer_covers_the_layout() {
+        assert_eq!(framebuffer_size(&layout()), (3840 + 2160, 3840));
+        // Measured from the top left monitor, wherever that is
+        let mut monitors = layout();
+        monitors[0].proposed_position = (-3840, 500);
+        monitors[1].proposed_position = (0, 0);
+        assert_eq!(framebuffer_size(&monitors), (6000, 3840));
+        // Off ones don't count, panning does
+        monitors[1].proposed_status = false;
+        monitors[0].proposed_panning.size = (5120, 2880);
+        assert_eq!(framebuffer_size(&monitors), (5120, 2880));
+    }
+
+    #[test]
+    fn exactly_at_the_maximum() {
+        assert_eq!(check_screen_size(&layout(), None, Some(&screen((6000, 3840)))), Ok(()));
+        assert_eq!(check_screen_size(&layout(), Some((6000, 3840)), Some(&screen((6000, 3840)))), Ok(()));
+        assert_eq!(check_screen_size(&layout(), None, None), Ok(()));
+    }
+
+    #[test]
+    fn over_the_maximum() {
+        assert_eq!(
+            check_screen_size(&layout(), None, Some(&screen((5999, 8192)))),
+            Err(String::from("DP-2 reaches 6000x3840 but the X screen can't be bigger than 5999x8192. Move it closer to the others, give it a smaller mode or turn panning down"))
+        );
+        // The layout fits but the picked screen size doesn't
+        assert_eq!(
+            check_screen_size(&layout(), Some((8192, 4096)), Some(&screen((8192, 4095)))),
+            Err(String::from("A 8192x4096 screen is bigger than the 8192x4095 the X screen can be"))
+        );
+    }
+
+    #[test]
+    fn picked_screen_size_out_of_range() {
+        assert_eq!(
+            check_screen_size(&layout(), Some((5999, 3840)), None),
+            Err(String::from("A 5999x3840 screen is too small, the layout needs at least 6000x3840"))
+        );
+        let monitors = vec![monitor("DP-1", (640, 480), (0, 0))];
+        assert_eq!(check_screen_size(&monitors, Some((640, 480)), Some(&screen((8192, 8192)))), Ok(()));
+        let mut tiny = screen((8192, 8192));
+        tiny.minimum = (800, 600);
+        assert_eq!(check_screen_size(&monitors, Some((640, 480)), Some(&tiny)), Err(String::from("The X screen can't be smaller than 800x600")));
+    }
+}
@@ -1,30 +1,192 @@
-use scrap::{Capturer, Display};
-use std::io::ErrorKind;
-use std::time::Instant;
-pub fn capture_screen(display: Display) -> Option<image::DynamicImage> {// Take a display inputed
-
-    let start = Instant::now();
-    let mut capturer = Capturer::new(display).expect("Couldn't begin capture.");
-        match capturer.frame() {
-            Ok(frame) => {
-                let buffer = frame.to_vec();
-                let width = capturer.width();
-                let height = capturer.height();
-
-                // Convert to an image
-                let image = image::ImageBuffer::<image::Bgra<u8>, Vec<u8>>::from_raw(width as u32, height as u32, buffer)
-                    .expect("Failed to convert the capture to an image");
-                // Make a bga image from the cap frame.
-                let duration = start.elapsed();
-                //println!("Time elapsed: {:?}", duration);
-                return Some(image::DynamicImage::ImageBgra8(image));  // Send it back with optionally being none. 
-            }
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                return None;
-            }
-            Err(e) => {
-                eprintln!("Error capturing screen: {:?}", e);
-                return None;
-            }
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::str;
+use x11rb::protocol::xproto::{ConnectionExt as _, ImageFormat, Window};
+use x11rb::rust_connection::RustConnection;
+
+// How hard to try when shrinking a frame down to the size it gets drawn on the canvas
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PreviewQuality {
+    Fast,
+    Balanced,
+    Best,
+}
+
+impl PreviewQuality {
+    pub const ALL: [PreviewQuality; 3] = [PreviewQuality::Fast, PreviewQuality::Balanced, PreviewQuality::Best];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PreviewQuality::Fast => "Fast",
+            PreviewQuality::Balanced => "Balanced",
+            PreviewQuality::Best => "Best",
+        }
+    }
+
+    fn filter(&self) -> image::FilterType {
+        match self {
+            PreviewQuality::Fast => image::FilterType::Nearest,
+            PreviewQuality::Balanced => image::FilterType::Triangle,
+            PreviewQuality::Best => image::FilterType::Lanczos3,
+        }
+    }
+}
+
+// What the previews are allowed to show. For screen sharing brandr without leaking chat and email
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PrivacyMode {
+    Off,
+    Blur, // Still captured but blurred past reading
+    Hide, // Never captured at all, just a solid color with the output name
+}
+
+impl PrivacyMode {
+    pub const ALL: [PrivacyMode; 3] = [PrivacyMode::Off, PrivacyMode::Blur, PrivacyMode::Hide];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PrivacyMode::Off => "Off",
+            PrivacyMode::Blur => "Blur",
+            PrivacyMode::Hide => "Hide",
+        }
+    }
+}
+
+// Grab one output's rectangle on the X screen, (x, y) and (width, height), and shrink the frame to target size before
+// it goes anywhere near the gpu. A 4k frame is ~33MB of pixels for a rect thats a couple hundred px wide so no point
+// carrying it around. Cut out of the root window by position so two identical monitors can't swap previews
+pub fn capture_screen(
+    connection: &RustConnection,
+    root: Window,
+    position: (i32, i32),
+    size: (u32, u32),
+    target: (u32, u32),
+    quality: PreviewQuality,
+    privacy: PrivacyMode,
+) -> Option<image::DynamicImage> {
+    if privacy == PrivacyMode::Hide {
+        return None; // Don't even grab the frame
+    }
+
+    let (width, height) = size;
+    // X coordinates are 16 bit. Anything past that isn't a real screen so just skip the frame
+    let (x, y) = (i16::try_from(position.0).ok()?, i16::try_from(position.1).ok()?);
+    let (request_width, request_height) = (u16::try_from(width).ok()?, u16::try_from(height).ok()?);
+    let reply = connection
+        .get_image(ImageFormat::Z_PIXMAP, root, x, y, request_width, request_height, !0)
+        .ok()?
+        .reply();
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(e) => {
+            eprintln!("Error capturing screen: {:?}", e);
+            return None;
+        }
+    };
+    // Only 24 and 32 bit come back as 4 byte BGRX. 30 bit packs the channels differently and 16 bit is 2 bytes a pixel
+    if reply.depth != 24 && reply.depth != 32 {
+        return None;
+    }
+    let mut buffer = reply.data;
+    if buffer.len() != width as usize * height as usize * 4 {
+        return None;
+    }
+    // 24 bit screens come back as BGRX. The X byte is junk and would make the texture see through
+    for pixel in buffer.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    // Convert to an image
+    let image = image::ImageBuffer::<image::Bgra<u8>, Vec<u8>>::from_raw(width, height, buffer)?;
+    let resized = shrink(image::DynamicImage::ImageBgra8(image), target, quality);
+    // Blur after shrinking so it's cheap. Scaled to the preview so text is gone at any canvas size
+    if privacy == PrivacyMode::Blur {
+        let (target_width, target_height) = (target.0.max(1), target.1.max(1));
+        return Some(resized.blur((target_width.max(target_height) as f32 / 40.0).max(2.0)));
+    }
+    Some(resized)
+}
+
+// Down to the size it gets drawn at. Never scales up, the canvas is smaller than the screen anyway
+pub fn shrink(captured: image::DynamicImage, target: (u32, u32), quality: PreviewQuality) -> image::DynamicImage {
+    let (width, height) = image::GenericImageView::dimensions(&captured);
+    let (target_width, target_height) = (target.0.max(1), target.1.max(1));
+    if target_width < width && target_height < height {
+        captured.resize_exact(target_width, target_height, quality.filter())
+    } else {
+        captured
+    }
+}
+
+// Where an output actually sits on the X screen. Straight from xrandr --listmonitors
+pub struct OutputGeometry {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub width_mm: u32,
+    pub height_mm: u32,
+    pub x: i32,
+    pub y: i32,
+    pub automatic: bool, // Made by X for an output. The rest came from --setmonitor
+    pub outputs: Vec<String>, // Outputs it covers
+}
+
+// Empty if xrandr can't be run. Callers treat that like no monitors being listed
+pub fn get_output_geometry() -> Vec<OutputGeometry> {
+    list_monitors().unwrap_or_default()
+}
+
+// Same list for callers where an empty one would do damage, like clearing every ICC atom
+pub fn list_monitors() -> Result<Vec<OutputGeometry>, String> {
+    let output = Command::new("xrandr")
+        .arg("--listmonitors")
+        .output()
+        .map_err(|e| format!("Failed to run xrandr: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("xrandr --listmonitors failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(parse_listmonitors(str::from_utf8(&output.stdout).unwrap_or_default()))
+}
+
+pub fn parse_listmonitors(output_str: &str) -> Vec<OutputGeometry> {
+    let mut geometry = Vec::new();
+    // Lines look like " 0: +*eDP-1 1920/344x1080/194+0+0  eDP-1". First line is just "Monitors: 2"
+    for line in output_str.lines().skip(1) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        let automatic = parts[1].starts_with('+');
+        let name = parts[1].trim_start_matches(|c| c == '+' || c == '*');
+        let outputs = parts[3..].iter().map(|output| output.to_string()).collect();
+
+        // 1920/344x1080/194+0+0 -> width/mm x height/mm + x + y
+        let size_position: Vec<&str> = parts[2].split('x').collect();
+        if size_position.len() != 2 {
+            continue;
+        }
+        let height_position: Vec<&str> = size_position[1].split('+').collect();
+        if height_position.len() != 3 {
+            continue;
+        }
+        let (width, width_mm) = size_position[0].split_once('/').unwrap_or((size_position[0], "0"));
+        let (height, height_mm) = height_position[0].split_once('/').unwrap_or((height_position[0], "0"));
+        let x = height_position[1].parse::<i32>();
+        let y = height_position[2].parse::<i32>();
+
+        if let (Ok(width), Ok(height), Ok(x), Ok(y)) = (width.parse::<u32>(), height.parse::<u32>(), x, y) {
+            geometry.push(OutputGeometry {
+                name: name.to_string(),
+                width,
+                height,
+                width_mm: width_mm.parse().unwrap_or(0),
+                height_mm: height_mm.parse().unwrap_or(0),
+                x,
+                y,
+                automatic,
+                outputs,
+            });
         }
+    }
+    geometry
 }
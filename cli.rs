@@ -0,0 +1,268 @@
+use crate::placement::{self, Align, Relation};
+use crate::{apply, config, get_monitors_from_xrandr, parse_resolution_input, profile, providers, vrr, xrandr, Monitor, ROTATIONS};
+use std::path::Path;
+
+const USAGE: &str = "Usage: brandr [command]
+With no command the gui opens.
+
+Commands:
+  list                      Show every connected output and its modes
+  connectors                Show every connector, plugged in or not. Disconnected ones that are still on get marked stale
+  off-disconnected          Turn off disconnected outputs that are still on
+  query --json              Print the current state as a JSON layout (schema in the README)
+  apply <profile>           Apply a saved profile
+  apply --json <file>       Apply a JSON layout. - reads stdin
+  save <profile>            Save the current layout as a profile
+  off <output>              Turn an output off
+  set <output> [options]    Change one output and leave the rest alone
+      --mode <W>x<H>
+      --rate <hz>
+      --rotate <normal|left|inverted|right>
+      --pos <X>x<Y>
+      --right-of <output>, --left-of <output>, --above <output>, --below <output>, --same-as <output>
+      --align <top|bottom|left|right|center>   Which edges line up with the other output. top/bottom beside it, left/right above or below. Defaults to top/left
+      --primary
+  night-light               Tint the screens like config.json's night light says for right now. For cron or autostart
+  icc                       Load the ICC profiles attached to the connected monitors. For udev rules or autostart
+  providers                 List the GPUs (RandR providers) and what they can do
+  provider-source <provider> <source|none>   Show what <source> renders on <provider>'s outputs. Run before set or apply
+  offload-sink <provider> <sink|none>        Let <provider> render apps that <sink> shows";
+
+// Entry point when brandr gets arguments. Returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let result = match args[0].as_str() {
+        "list" => list(),
+        "connectors" => list_connectors(),
+        "off-disconnected" => off_disconnected(),
+        "query" => match args.get(1).map(|arg| arg.as_str()) {
+            Some("--json") => query_json(),
+            _ => Err(String::from("query needs --json")),
+        },
+        "apply" => match (args.get(1).map(|arg| arg.as_str()), args.get(2)) {
+            (Some("--json"), Some(file)) => apply_json(file),
+            (Some("--json"), None) => Err(String::from("apply --json needs a file")),
+            (Some(name), _) => apply_profile(name),
+            (None, _) => Err(String::from("apply needs a profile name")),
+        },
+        "save" => match args.get(1) {
+            Some(name) => save(name),
+            None => Err(String::from("save needs a profile name")),
+        },
+        "off" => match args.get(1) {
+            Some(id) => off(id),
+            None => Err(String::from("off needs an output")),
+        },
+        "set" => match args.get(1) {
+            Some(id) => set(id, &args[2..]),
+            None => Err(String::from("set needs an output")),
+        },
+        "night-light" | "icc" => color(),
+        "providers" => list_providers(),
+        "provider-source" => match (args.get(1), args.get(2)) {
+            (Some(provider), Some(source)) => providers::set_output_source(provider, linked_to(source)),
+            _ => Err(String::from("provider-source needs a provider and a source")),
+        },
+        "offload-sink" => match (args.get(1), args.get(2)) {
+            (Some(provider), Some(sink)) => providers::set_offload_sink(provider, linked_to(sink)),
+            _ => Err(String::from("offload-sink needs a provider and a sink")),
+        },
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        other => Err(format!("Unknown command {}\n\n{}", other, USAGE)),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn list() -> Result<(), String> {
+    for monitor in get_monitors_from_xrandr()?.0 {
+        let name = match monitor.edid.as_ref().and_then(|edid| edid.name.as_ref()) {
+            Some(edid_name) => format!("{} ({})", monitor.id, edid_name),
+            None => monitor.id.clone(),
+        };
+        let primary = if monitor.primary { " primary" } else { "" };
+        let variable_refresh = if vrr::capable(&monitor.properties) { " vrr" } else { "" };
+        if monitor.enabled {
+            let (width, height) = monitor.resolution;
+            let rate = monitor.rate.map(|rate| format!(" @ {:.2}", rate)).unwrap_or_default();
+            println!(
+                "{}{}{} {}x{}{} +{}+{} {}",
+                name, primary, variable_refresh, width, height, rate, monitor.proposed_position.0, monitor.proposed_position.1, monitor.orientation
+            );
+        } else {
+            println!("{}{}{} off", name, primary, variable_refresh);
+        }
+        for ((width, height), rates) in &monitor.modes {
+            let rates: Vec<String> = rates.iter().map(|rate| format!("{:.2}", rate)).collect();
+            println!("    {}x{} {}", width, height, rates.join(" "));
+        }
+    }
+    Ok(())
+}
+
+fn list_connectors() -> Result<(), String> {
+    for connector in xrandr::get_connectors() {
+        let area = connector.crtc.map_or(String::from("off"), |area| area.arg());
+        let stale = if connector.is_stale() { " stale" } else { "" };
+        println!("{} {} {}{}", connector.id, connector.connection.label(), area, stale);
+    }
+    Ok(())
+}
+
+fn off_disconnected() -> Result<(), String> {
+    let stale: Vec<String> = xrandr::get_connectors()
+        .into_iter()
+        .filter(|connector| connector.is_stale())
+        .map(|connector| connector.id)
+        .collect();
+    for id in &stale {
+        println!("Turning off {}", id);
+    }
+    apply::turn_off(&stale)
+}
+
+// "none" unlinks
+fn linked_to(provider: &str) -> Option<&str> {
+    Some(provider).filter(|provider| *provider != "none")
+}
+
+fn list_providers() -> Result<(), String> {
+    for provider in providers::get_providers() {
+        println!(
+            "{} {}: {} crtcs, {} outputs, linked with {}",
+            provider.id, provider.name, provider.crtcs, provider.outputs, provider.associated
+        );
+        println!("    {}", provider.capabilities.join(", "));
+    }
+    Ok(())
+}
+
+fn query_json() -> Result<(), String> {
+    let layout = profile::Layout::from_monitors(&get_monitors_from_xrandr()?.0);
+    let json = serde_json::to_string_pretty(&layout).map_err(|e| e.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn apply_profile(name: &str) -> Result<(), String> {
+    apply_layout(&profile::load_profile(name)?, name)
+}
+
+fn apply_json(file: &str) -> Result<(), String> {
+    apply_layout(&profile::load_layout_file(Path::new(file))?, file)
+}
+
+fn apply_layout(layout: &profile::Layout, name: &str) -> Result<(), String> {
+    let (mut monitors, screen) = get_monitors_from_xrandr()?;
+    for missing in layout.apply_to(&mut monitors) {
+        eprintln!("{} is in {} but isn't connected, skipping it", missing, name);
+    }
+    apply::apply_monitors(&mut monitors, None, screen.as_ref(), config::load_config().night_light.active_temperature())
+}
+
+fn save(name: &str) -> Result<(), String> {
+    let (monitors, _) = get_monitors_from_xrandr()?;
+    let path = profile::save_profile(name, &profile::Layout::from_monitors(&monitors))?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+fn off(id: &str) -> Result<(), String> {
+    let (mut monitors, screen) = get_monitors_from_xrandr()?;
+    let index = find_monitor(&monitors, id)?;
+    monitors[index].proposed_status = false;
+    apply::apply_monitors(&mut monitors, None, screen.as_ref(), config::load_config().night_light.active_temperature())
+}
+
+// Both put the whole color state back. Night light and calibration share the gamma ramps so one can't go on without the other
+fn color() -> Result<(), String> {
+    let (monitors, _) = get_monitors_from_xrandr()?;
+    apply::apply_color(&monitors, config::load_config().night_light.active_temperature())
+}
+
+fn set(id: &str, options: &[String]) -> Result<(), String> {
+    let (mut monitors, screen) = get_monitors_from_xrandr()?;
+    let index = find_monitor(&monitors, id)?;
+    monitors[index].proposed_status = true;
+
+    let mut relative_to: Option<(Relation, String)> = None; // (--right-of etc, other output) worked out after the mode is known
+    let mut align: Option<String> = None; // Checked against the relation once every option is read
+    let mut rate: Option<f32> = None; // Goes on after --mode, which clears the rate, wherever it was on the line
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().cloned().ok_or_else(|| format!("{} needs a value", option));
+        match option.as_str() {
+            "--mode" => {
+                let mode = value()?;
+                let mode = parse_resolution_input(&mode).ok_or_else(|| format!("Bad mode {}, expected WxH", mode))?;
+                let monitor = &mut monitors[index];
+                if !monitor.modes.is_empty() && !monitor.modes.iter().any(|(size, _)| *size == mode) {
+                    return Err(format!("{} doesn't support {}x{}. Run brandr list to see its modes", id, mode.0, mode.1));
+                }
+                monitor.proposed_resolution = Some(mode);
+                monitor.proposed_rate = None; // The old rate might not exist on the new mode. Let xrandr pick unless --rate says
+            }
+            "--rate" => {
+                let value = value()?;
+                rate = Some(value.parse::<f32>().map_err(|_| format!("Bad rate {}", value))?);
+            }
+            "--rotate" => {
+                let rotation = value()?;
+                if !ROTATIONS.contains(&rotation.as_str()) {
+                    return Err(format!("Bad rotation {}, expected one of {}", rotation, ROTATIONS.join(", ")));
+                }
+                monitors[index].proposed_orientation = rotation;
+            }
+            "--pos" => {
+                let pos = value()?;
+                let (x, y) = pos.split_once('x').ok_or_else(|| format!("Bad position {}, expected XxY", pos))?;
+                let x = x.parse::<i32>().map_err(|_| format!("Bad position {}", pos))?;
+                let y = y.parse::<i32>().map_err(|_| format!("Bad position {}", pos))?;
+                monitors[index].proposed_position = (x, y);
+            }
+            "--right-of" | "--left-of" | "--above" | "--below" | "--same-as" => {
+                let relation = Relation::parse(option).expect("matched above");
+                relative_to = Some((relation, value()?));
+            }
+            "--align" => align = Some(value()?),
+            "--primary" => {
+                for monitor in monitors.iter_mut() {
+                    monitor.proposed_primary = false;
+                }
+                monitors[index].proposed_primary = true;
+            }
+            other => return Err(format!("Unknown option {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    if rate.is_some() {
+        monitors[index].proposed_rate = rate;
+    }
+    if let Some((relation, other_id)) = relative_to {
+        let align = match align {
+            Some(name) => Align::parse_for(&name, relation)?,
+            None => Align::Start,
+        };
+        let other = find_monitor(&monitors, &other_id)?;
+        placement::place(&mut monitors, index, relation, align, other)?;
+    } else if align.is_some() {
+        return Err(String::from("--align only works with --right-of, --left-of, --above, --below or --same-as"));
+    }
+
+    apply::apply_monitors(&mut monitors, None, screen.as_ref(), config::load_config().night_light.active_temperature())
+}
+
+fn find_monitor(monitors: &[Monitor], id: &str) -> Result<usize, String> {
+    monitors
+        .iter()
+        .position(|monitor| monitor.id == id)
+        .ok_or_else(|| format!("No connected output called {}", id))
+}
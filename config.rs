@@ -0,0 +1,59 @@
+use crate::cap::{PreviewQuality, PrivacyMode};
+use crate::icc;
+use crate::night_light::NightLight;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// Defaults the gui starts with. Lives in ~/.config/brandr/config.json and anything left out falls back to these
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub privacy: PrivacyMode,
+    pub preview_quality: PreviewQuality,
+    pub night_light: NightLight,
+    pub icc_profiles: Vec<icc::Assignment>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            privacy: PrivacyMode::Off,
+            preview_quality: PreviewQuality::Fast, // Cheapest, and at canvas size it looks the same. See examples/preview_benchmark.rs
+            night_light: NightLight::default(),
+            icc_profiles: Vec::new(),
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/brandr or ~/.config/brandr
+pub fn config_dir() -> PathBuf {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config"),
+    };
+    base.join("brandr")
+}
+
+pub fn load_config() -> Config {
+    let path = config_dir().join("config.json");
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Ignoring config {}: {}", path.display(), e);
+            Config::default()
+        }),
+        Err(_) => Config::default(), // No config is fine
+    }
+}
+
+// The gui writes back the settings that have to survive a restart (night light, ICC profiles). Reads the file
+// again first so the rest of it stays how it was
+pub fn update_config(change: impl FnOnce(&mut Config)) -> Result<(), String> {
+    let mut config = load_config();
+    change(&mut config);
+    let dir = config_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+    let path = dir.join("config.json");
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+}
@@ -0,0 +1,246 @@
+use crate::xrandr::{EdidIdentity, VerboseOutput};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// Hardware settings through the monitor's own controls (DDC/CI). Actually changes the backlight unlike --brightness.
+// Needs the i2c-dev module loaded and read/write on /dev/i2c-*, usually by being in the i2c group
+
+const DDC_ADDRESS: u16 = 0x37; // Where monitors listen for DDC/CI
+const HOST_ADDRESS: u8 = 0x51; // Source address we send as
+const I2C_SLAVE: libc::c_ulong = 0x0703; // ioctl to pick the device address on an i2c-dev file
+
+pub const VCP_BRIGHTNESS: u8 = 0x10;
+pub const VCP_CONTRAST: u8 = 0x12;
+pub const VCP_INPUT_SOURCE: u8 = 0x60;
+
+// Anything that can move bytes to and from an i2c address. The real one is /dev/i2c-N, the tests below use a fake monitor
+pub trait I2cBus {
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()>;
+    fn read(&mut self, address: u16, buffer: &mut [u8]) -> io::Result<()>;
+}
+
+pub struct DevI2c {
+    file: File,
+}
+
+impl DevI2c {
+    pub fn open(path: &Path) -> io::Result<DevI2c> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(DevI2c { file })
+    }
+
+    fn select(&mut self, address: u16) -> io::Result<()> {
+        // Safe, the fd is ours and I2C_SLAVE only takes the address
+        let result = unsafe { libc::ioctl(self.file.as_raw_fd(), I2C_SLAVE as _, address as libc::c_ulong) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl I2cBus for DevI2c {
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.write_all(data)
+    }
+
+    fn read(&mut self, address: u16, buffer: &mut [u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.read_exact(buffer)
+    }
+}
+
+// DDC/CI checksum is every byte XORed, starting with the destination address shifted like it is on the wire
+fn checksum(first: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(first, |sum, byte| sum ^ byte)
+}
+
+// Current and maximum value of a VCP code
+pub fn get_vcp(bus: &mut impl I2cBus, code: u8) -> Result<(u16, u16), String> {
+    let mut request = vec![HOST_ADDRESS, 0x82, 0x01, code];
+    request.push(checksum((DDC_ADDRESS << 1) as u8, &request));
+    bus.write(DDC_ADDRESS, &request).map_err(|e| format!("DDC write failed: {}", e))?;
+    thread::sleep(Duration::from_millis(40)); // Monitors need a moment before they answer
+
+    // 6E 88 02 result code type max_hi max_lo current_hi current_lo checksum
+    let mut reply = [0u8; 11];
+    bus.read(DDC_ADDRESS, &mut reply).map_err(|e| format!("DDC read failed: {}", e))?;
+    if checksum(0x50, &reply[..10]) != reply[10] {
+        return Err(String::from("DDC reply had a bad checksum"));
+    }
+    if reply[2] != 0x02 || reply[4] != code {
+        return Err(format!("Unexpected DDC reply for VCP {:#04x}", code));
+    }
+    if reply[3] != 0x00 {
+        return Err(format!("Monitor doesn't support VCP {:#04x}", code));
+    }
+    let max = u16::from_be_bytes([reply[6], reply[7]]);
+    let current = u16::from_be_bytes([reply[8], reply[9]]);
+    Ok((current, max))
+}
+
+pub fn set_vcp(bus: &mut impl I2cBus, code: u8, value: u16) -> Result<(), String> {
+    let [high, low] = value.to_be_bytes();
+    let mut request = vec![HOST_ADDRESS, 0x84, 0x03, code, high, low];
+    request.push(checksum((DDC_ADDRESS << 1) as u8, &request));
+    bus.write(DDC_ADDRESS, &request).map_err(|e| format!("DDC write failed: {}", e))?;
+    thread::sleep(Duration::from_millis(50)); // Spec says wait before talking to it again
+    Ok(())
+}
+
+// What the settings panel shows. None for anything the monitor wouldn't answer
+pub struct Controls {
+    pub bus: PathBuf,
+    pub brightness: Option<(u16, u16)>, // (current, max)
+    pub contrast: Option<(u16, u16)>,
+    pub input_source: Option<u16>,
+}
+
+impl Controls {
+    pub fn read(bus_path: PathBuf) -> Result<Controls, String> {
+        let mut bus = DevI2c::open(&bus_path).map_err(|e| format!("Can't open {}: {}", bus_path.display(), e))?;
+        let brightness = get_vcp(&mut bus, VCP_BRIGHTNESS).ok();
+        let contrast = get_vcp(&mut bus, VCP_CONTRAST).ok();
+        let input_source = get_vcp(&mut bus, VCP_INPUT_SOURCE).ok().map(|(current, _)| current & 0xFF);
+        if brightness.is_none() && contrast.is_none() && input_source.is_none() {
+            return Err(format!("No DDC/CI answer on {}. It might be turned off in the monitor's menu", bus_path.display()));
+        }
+        Ok(Controls { bus: bus_path, brightness, contrast, input_source })
+    }
+}
+
+// Write one VCP code on the monitor at bus_path
+pub fn set(bus_path: &Path, code: u8, value: u16) -> Result<(), String> {
+    let mut bus = DevI2c::open(bus_path).map_err(|e| format!("Can't open {}: {}", bus_path.display(), e))?;
+    set_vcp(&mut bus, code, value)
+}
+
+// MCCS input source values. Monitors use whichever subset they have
+pub const INPUT_SOURCES: [(u16, &str); 10] = [
+    (0x01, "VGA 1"),
+    (0x02, "VGA 2"),
+    (0x03, "DVI 1"),
+    (0x04, "DVI 2"),
+    (0x0F, "DisplayPort 1"),
+    (0x10, "DisplayPort 2"),
+    (0x11, "HDMI 1"),
+    (0x12, "HDMI 2"),
+    (0x1B, "USB-C 1"),
+    (0x1C, "USB-C 2"),
+];
+
+pub fn input_source_label(value: u16) -> String {
+    INPUT_SOURCES
+        .iter()
+        .find(|(source, _)| *source == value)
+        .map(|(_, label)| label.to_string())
+        .unwrap_or_else(|| format!("Input {:#04x}", value))
+}
+
+// The /dev/i2c-N the DRM connector for this output talks DDC on. Connectors are matched on EDID since DRM and
+// xrandr don't always name them the same (HDMI-A-1 vs HDMI-1, DisplayPort-0 vs DP-1), then on name as a fallback
+pub fn find_bus(output: &str, edid: Option<&EdidIdentity>) -> Option<PathBuf> {
+    let mut enumerator = udev::Enumerator::new().ok()?;
+    enumerator.match_subsystem("drm").ok()?;
+    let mut by_name = None;
+    for device in enumerator.scan_devices().ok()? {
+        let sysname = device.sysname().to_string_lossy().to_string(); // card0-HDMI-A-1
+        let Some((_, connector)) = sysname.split_once('-') else { continue }; // card0 itself has no connector
+        let syspath = device.syspath();
+
+        let connector_edid = VerboseOutput { edid: fs::read(syspath.join("edid")).unwrap_or_default(), ..Default::default() };
+        let same_edid = edid.is_some() && connector_edid.edid_identity().as_ref() == edid;
+        let same_name = connector == output || connector.replacen("-A-", "-", 1) == output;
+        if !same_edid && !same_name {
+            continue;
+        }
+        let Some(bus) = ddc_bus(syspath) else { continue };
+        if same_edid {
+            return Some(bus);
+        }
+        by_name = Some(bus);
+    }
+    by_name
+}
+
+// Most drivers link the connector's DDC adapter as `ddc`. DisplayPort ones often have the AUX channel's i2c-N inside instead
+fn ddc_bus(connector: &Path) -> Option<PathBuf> {
+    let adapter = fs::read_link(connector.join("ddc"))
+        .ok()
+        .and_then(|link| link.file_name().map(|name| name.to_string_lossy().to_string()))
+        .or_else(|| {
+            fs::read_dir(connector).ok()?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .find(|name| name.starts_with("i2c-"))
+        })?;
+    Some(Path::new("/dev").join(adapter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monitor that answers every read with `reply` and keeps what got written
+    struct FakeMonitor {
+        written: Vec<(u16, Vec<u8>)>,
+        reply: Vec<u8>,
+    }
+
+    impl I2cBus for FakeMonitor {
+        fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()> {
+            self.written.push((address, data.to_vec()));
+            Ok(())
+        }
+
+        fn read(&mut self, _address: u16, buffer: &mut [u8]) -> io::Result<()> {
+            buffer.copy_from_slice(&self.reply);
+            Ok(())
+        }
+    }
+
+    // Get VCP reply for `code` at 42 out of 100, checksum included
+    fn reply(code: u8) -> Vec<u8> {
+        let mut reply = vec![0x6E, 0x88, 0x02, 0x00, code, 0x00, 0x00, 100, 0x00, 42];
+        reply.push(checksum(0x50, &reply));
+        reply
+    }
+
+    #[test]
+    fn get_vcp_reads_current_and_max() {
+        let mut monitor = FakeMonitor { written: Vec::new(), reply: reply(VCP_BRIGHTNESS) };
+        assert_eq!(get_vcp(&mut monitor, VCP_BRIGHTNESS), Ok((42, 100)));
+        let request = vec![0x51, 0x82, 0x01, VCP_BRIGHTNESS, 0x6E ^ 0x51 ^ 0x82 ^ 0x01 ^ VCP_BRIGHTNESS];
+        assert_eq!(monitor.written, vec![(DDC_ADDRESS, request)]);
+    }
+
+    #[test]
+    fn get_vcp_rejects_bad_checksum() {
+        let mut bad = reply(VCP_CONTRAST);
+        bad[10] ^= 0xFF;
+        let mut monitor = FakeMonitor { written: Vec::new(), reply: bad };
+        assert_eq!(get_vcp(&mut monitor, VCP_CONTRAST), Err(String::from("DDC reply had a bad checksum")));
+    }
+
+    #[test]
+    fn get_vcp_rejects_unsupported_code() {
+        let mut unsupported = reply(VCP_INPUT_SOURCE);
+        unsupported[3] = 0x01;
+        unsupported[10] = checksum(0x50, &unsupported[..10]);
+        let mut monitor = FakeMonitor { written: Vec::new(), reply: unsupported };
+        assert!(get_vcp(&mut monitor, VCP_INPUT_SOURCE).is_err());
+    }
+
+    #[test]
+    fn set_vcp_frames_the_value() {
+        let mut monitor = FakeMonitor { written: Vec::new(), reply: Vec::new() };
+        set_vcp(&mut monitor, VCP_BRIGHTNESS, 0x0150).unwrap();
+        let request = vec![0x51, 0x84, 0x03, VCP_BRIGHTNESS, 0x01, 0x50, 0x6E ^ 0x51 ^ 0x84 ^ 0x03 ^ VCP_BRIGHTNESS ^ 0x01 ^ 0x50];
+        assert_eq!(monitor.written, vec![(DDC_ADDRESS, request)]);
+    }
+}
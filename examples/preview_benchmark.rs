@@ -0,0 +1,50 @@
+// How long shrinking and uploading a preview takes at each quality. The numbers in the README come from this.
+// cargo run --release --example preview_benchmark
+use eframe::egui::{self, ColorImage, TextureOptions};
+use std::time::{Duration, Instant};
+
+#[allow(dead_code)] // Only the shrinking is used here
+#[path = "../cap.rs"]
+mod cap;
+
+use cap::{shrink, PreviewQuality};
+
+// Average over `runs` calls
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    // A 4k frame going into a rect about as big as one on the canvas
+    let frame = image::DynamicImage::ImageBgra8(image::ImageBuffer::from_fn(3840, 2160, |x, y| {
+        image::Bgra([x as u8, y as u8, (x ^ y) as u8, 255])
+    }));
+    let target = (300, 169);
+    let copy = time(10, || drop(frame.clone()));
+    println!("copying the frame       {:?} (included in the ones below)", copy);
+    for quality in PreviewQuality::ALL {
+        let took = time(10, || drop(shrink(frame.clone(), target, quality)));
+        println!("shrink {:<16} {:?}", quality.label(), took);
+    }
+
+    // Running a frame hands the queued texture data over like the real app does, so nothing piles up
+    let ctx = egui::Context::default();
+    let full = ColorImage::from_rgba_unmultiplied([3840, 2160], &frame.to_rgba());
+    let small = ColorImage::from_rgba_unmultiplied([300, 169], &shrink(frame.clone(), target, PreviewQuality::Fast).to_rgba());
+    for (label, image) in [("full size", &full), ("canvas size", &small)] {
+        let load = time(20, || {
+            drop(ctx.load_texture("benchmark", image.clone(), TextureOptions::default()));
+            drop(ctx.run(Default::default(), |_| {}));
+        });
+        let mut texture = ctx.load_texture("benchmark", image.clone(), TextureOptions::default());
+        let set = time(20, || {
+            texture.set(image.clone(), TextureOptions::default());
+            drop(ctx.run(Default::default(), |_| {}));
+        });
+        println!("{:<12} load_texture {:?}, set {:?}", label, load, set);
+    }
+}
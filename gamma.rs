@@ -0,0 +1,128 @@
+use crate::icc::Vcgt;
+use crate::night_light::{self, NEUTRAL_TEMPERATURE};
+use crate::Monitor;
+use std::collections::HashMap;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, PropMode, Window};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+// CRTC gamma ramps straight through RandR. xrandr only knows brightness and gamma, brandr also puts the night light
+// tint and ICC calibration curves in, so once either is on the ramps get made here
+
+const SHOWN_ATOM: &[u8] = b"_BRANDR_NIGHT_LIGHT"; // Root window property with the temperature brandr last put on
+
+// Ramps for one CRTC. Same curve xrandr makes for --brightness and --gamma, through the calibration if there is one, then tinted
+pub fn ramps(size: usize, kelvin: u32, brightness: f32, gamma: [f32; 3], calibration: Option<&Vcgt>) -> [Vec<u16>; 3] {
+    let white = night_light::whitepoint(kelvin);
+    [0, 1, 2].map(|channel| {
+        (0..size)
+            .map(|step| {
+                let mut level = step as f64 / (size.max(2) - 1) as f64;
+                if let Some(calibration) = calibration {
+                    level = calibration.apply(channel, level);
+                }
+                let value = level.powf(1.0 / gamma[channel] as f64) * brightness as f64 * white[channel];
+                (value.clamp(0.0, 1.0) * 65535.0) as u16
+            })
+            .collect()
+    })
+}
+
+pub fn x_error(e: impl std::fmt::Display) -> String {
+    format!("X server: {}", e)
+}
+
+pub fn connect() -> Result<(RustConnection, Window), String> {
+    let (connection, screen) = x11rb::connect(None).map_err(x_error)?;
+    let root = connection.setup().roots[screen].root;
+    Ok((connection, root))
+}
+
+// Output name and the CRTC driving it, for every output that's on
+fn output_crtcs(connection: &RustConnection, root: Window) -> Result<Vec<(String, randr::Crtc)>, String> {
+    let resources = connection.randr_get_screen_resources_current(root).map_err(x_error)?.reply().map_err(x_error)?;
+    let mut crtcs = Vec::new();
+    for output in resources.outputs {
+        let info = connection.randr_get_output_info(output, resources.config_timestamp).map_err(x_error)?.reply().map_err(x_error)?;
+        if info.crtc != 0 {
+            crtcs.push((String::from_utf8_lossy(&info.name).to_string(), info.crtc));
+        }
+    }
+    Ok(crtcs)
+}
+
+// Ramps for every enabled monitor from the brightness and gamma it's showing, its calibration, and the `kelvin` tint
+// (None for untinted). Remembers the tint on the root window so the next read can undo it
+pub fn set_ramps(monitors: &[Monitor], kelvin: Option<u32>) -> Result<(), String> {
+    let (connection, root) = connect()?;
+    let crtcs = output_crtcs(&connection, root)?;
+    for monitor in monitors.iter().filter(|monitor| monitor.enabled) {
+        let Some((_, crtc)) = crtcs.iter().find(|(name, _)| *name == monitor.id) else { continue };
+        let size = connection.randr_get_crtc_gamma_size(*crtc).map_err(x_error)?.reply().map_err(x_error)?.size;
+        let (brightness, gamma) = monitor.shown_color;
+        let [red, green, blue] = ramps(size as usize, kelvin.unwrap_or(NEUTRAL_TEMPERATURE), brightness, gamma, monitor.calibration());
+        connection.randr_set_crtc_gamma(*crtc, &red, &green, &blue).map_err(x_error)?.check().map_err(x_error)?;
+    }
+
+    let atom = connection.intern_atom(false, SHOWN_ATOM).map_err(x_error)?.reply().map_err(x_error)?.atom;
+    match kelvin {
+        Some(kelvin) => connection.change_property32(PropMode::REPLACE, root, atom, AtomEnum::CARDINAL, &[kelvin]).map_err(x_error)?.check(),
+        None => connection.delete_property(root, atom).map_err(x_error)?.check(),
+    }
+    .map_err(x_error)
+}
+
+// Temperature brandr last tinted the screens to. On the X server itself so it goes away along with the ramps
+pub fn shown_temperature() -> Option<u32> {
+    let (connection, root) = connect().ok()?;
+    let atom = connection.intern_atom(true, SHOWN_ATOM).ok()?.reply().ok()?.atom;
+    if atom == 0 {
+        return None; // Never been set on this server
+    }
+    let reply = connection.get_property(false, root, atom, AtomEnum::CARDINAL, 0, 1).ok()?.reply().ok()?;
+    let kelvin = reply.value32()?.next()?;
+    Some(kelvin)
+}
+
+// Brightness and gamma under the tint for every output that's on. xrandr --verbose only sees the tinted ramps and
+// would report a made up gamma. One connection for all of them, get_monitors_from_xrandr wants every output anyway
+pub fn untinted_colors(kelvin: u32) -> HashMap<String, (f32, [f32; 3])> {
+    let mut colors = HashMap::new();
+    let Ok((connection, root)) = connect() else { return colors };
+    let Ok(crtcs) = output_crtcs(&connection, root) else { return colors };
+    for (output, crtc) in crtcs {
+        let Some(ramps) = connection.randr_get_crtc_gamma(crtc).ok().and_then(|cookie| cookie.reply().ok()) else { continue };
+        if let Some(color) = untint(&ramps, kelvin) {
+            colors.insert(output, color);
+        }
+    }
+    colors
+}
+
+// Worked out the same way xrandr does it, from the top of the ramp and the middle of it
+fn untint(ramps: &randr::GetCrtcGammaReply, kelvin: u32) -> Option<(f32, [f32; 3])> {
+    let white = night_light::whitepoint(kelvin);
+    let channels = [&ramps.red, &ramps.green, &ramps.blue];
+    let size = ramps.red.len();
+    if size < 3 {
+        return None;
+    }
+    let untinted = |channel: usize, step: usize| channels[channel][step] as f64 / 65535.0 / white[channel].max(0.05);
+
+    let brightness = (0..3).map(|channel| untinted(channel, size - 1)).fold(0.0, f64::max).min(1.0);
+    if brightness <= 0.0 {
+        return None;
+    }
+    let middle = size / 2;
+    let level = middle as f64 / (size - 1) as f64;
+    let gamma = [0, 1, 2].map(|channel| {
+        let exponent = (untinted(channel, middle) / brightness).ln() / level.ln();
+        if white[channel] < 0.05 || !exponent.is_finite() || exponent <= 0.0 {
+            return 1.0; // Tinted all the way off, nothing left to tell the gamma from
+        }
+        ((1.0 / exponent) * 100.0).round() as f32 / 100.0
+    });
+    Some(((brightness * 100.0).round() as f32 / 100.0, gamma))
+}
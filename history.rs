@@ -0,0 +1,201 @@
+use crate::modeline::Modeline;
+use crate::panning::Panning;
+use crate::virtual_monitor::VirtualMonitor;
+use crate::xrandr::OutputProperty;
+use crate::Monitor;
+
+// Everything the user can propose for one output. Undo and redo swap these back and forth
+#[derive(Clone, PartialEq)]
+struct ProposedOutput {
+    id: String,
+    status: bool,
+    resolution: Option<(u32, u32)>,
+    rate: Option<f32>,
+    position: Option<(i32, i32)>, // Left out for outputs that stay off. The canvas parks those wherever it likes
+    orientation: String,
+    primary: bool,
+    virtual_monitors: Vec<VirtualMonitor>,
+    brightness: f32,
+    gamma: [f32; 3],
+    properties: Vec<OutputProperty>,
+    panning: Panning,
+    new_modes: Vec<Modeline>, // Custom modes still waiting for Apply
+}
+
+#[derive(Clone, PartialEq)]
+struct Snapshot(Vec<ProposedOutput>);
+
+impl Snapshot {
+    fn take(monitors: &[Monitor]) -> Snapshot {
+        Snapshot(
+            monitors
+                .iter()
+                .map(|monitor| ProposedOutput {
+                    id: monitor.id.clone(),
+                    status: monitor.proposed_status,
+                    resolution: monitor.proposed_resolution,
+                    rate: monitor.proposed_rate,
+                    position: Some(monitor.proposed_position).filter(|_| monitor.proposed_status),
+                    orientation: monitor.proposed_orientation.clone(),
+                    primary: monitor.proposed_primary,
+                    virtual_monitors: monitor.proposed_virtual.clone(),
+                    brightness: monitor.proposed_brightness,
+                    gamma: monitor.proposed_gamma,
+                    properties: monitor.proposed_properties.clone(),
+                    panning: monitor.proposed_panning,
+                    new_modes: monitor.new_modes.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    // Matched up by id so it still works if the monitor list changed in between
+    fn restore(&self, monitors: &mut [Monitor]) {
+        for output in &self.0 {
+            if let Some(monitor) = monitors.iter_mut().find(|monitor| monitor.id == output.id) {
+                monitor.proposed_status = output.status;
+                monitor.proposed_resolution = output.resolution;
+                monitor.proposed_rate = output.rate;
+                if let Some(position) = output.position {
+                    monitor.proposed_position = position;
+                }
+                monitor.proposed_orientation = output.orientation.clone();
+                monitor.proposed_primary = output.primary;
+                monitor.proposed_virtual = output.virtual_monitors.clone();
+                monitor.proposed_brightness = output.brightness;
+                monitor.proposed_gamma = output.gamma;
+                monitor.proposed_properties = output.properties.clone();
+                monitor.proposed_panning = output.panning;
+                // Through the same path as the Custom mode window so the mode lists keep up
+                if monitor.new_modes != output.new_modes {
+                    for modeline in std::mem::take(&mut monitor.new_modes) {
+                        monitor.forget_mode(&modeline);
+                    }
+                    for modeline in &output.new_modes {
+                        monitor.add_custom_mode(modeline.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Undo/redo for layout edits. Instead of every widget pushing commands, the proposed state gets compared to the last
+// settled one each frame and any difference becomes one step. Skipping frames mid drag or mid typing is what coalesces a gesture
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    settled: Option<Snapshot>,
+}
+
+impl History {
+    pub fn record(&mut self, monitors: &[Monitor]) {
+        let now = Snapshot::take(monitors);
+        match self.settled.take() {
+            Some(last) if last != now => {
+                self.undo.push(last);
+                self.redo.clear();
+            }
+            _ => {}
+        }
+        self.settled = Some(now);
+    }
+
+    // For changes that didn't come from the user, like a refresh, a hotplug or an Apply. They become the new starting
+    // point instead of an undo step, so Undo still goes back to the user's last edit
+    pub fn rebase(&mut self, monitors: &[Monitor]) {
+        self.settled = Some(Snapshot::take(monitors));
+    }
+
+    pub fn undo(&mut self, monitors: &mut [Monitor]) {
+        if let Some(previous) = self.undo.pop() {
+            previous.restore(monitors);
+            if let Some(settled) = self.settled.replace(previous) {
+                self.redo.push(settled);
+            }
+        }
+    }
+
+    pub fn redo(&mut self, monitors: &mut [Monitor]) {
+        if let Some(next) = self.redo.pop() {
+            next.restore(monitors);
+            if let Some(settled) = self.settled.replace(next) {
+                self.undo.push(settled);
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modeline::{self, Timing};
+
+    fn monitor(id: &str) -> Monitor {
+        Monitor {
+            id: id.to_string(),
+            proposed_status: true,
+            modes: vec![((1920, 1080), vec![60.0])],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_an_edit() {
+        let mut monitors = vec![monitor("DP-1")];
+        let mut history = History::default();
+        history.record(&monitors);
+        monitors[0].proposed_position = (1920, 0);
+        history.record(&monitors);
+
+        history.undo(&mut monitors);
+        assert_eq!(monitors[0].proposed_position, (0, 0));
+        history.redo(&mut monitors);
+        assert_eq!(monitors[0].proposed_position, (1920, 0));
+    }
+
+    #[test]
+    fn rebase_skips_outside_changes() {
+        let mut monitors = vec![monitor("DP-1")];
+        let mut history = History::default();
+        history.record(&monitors);
+        monitors[0].proposed_position = (1920, 0);
+        history.record(&monitors);
+
+        // Hotplug moved it. Undo should take back the user's move, not this one
+        monitors[0].proposed_position = (2560, 0);
+        history.rebase(&monitors);
+        history.record(&monitors);
+        history.undo(&mut monitors);
+        assert_eq!(monitors[0].proposed_position, (0, 0));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn undo_takes_back_a_custom_mode() {
+        let mut monitors = vec![monitor("DP-1")];
+        let mut history = History::default();
+        history.record(&monitors);
+        let modeline = modeline::generate(2560, 1080, 75.0, Timing::CvtReducedBlanking);
+        monitors[0].add_custom_mode(modeline.clone());
+        history.record(&monitors);
+
+        history.undo(&mut monitors);
+        assert!(monitors[0].new_modes.is_empty());
+        assert_eq!(monitors[0].modes, vec![((1920, 1080), vec![60.0])]);
+        assert!(monitors[0].mode_names.is_empty());
+
+        history.redo(&mut monitors);
+        assert!(monitors[0].new_modes == vec![modeline]);
+        assert_eq!(monitors[0].modes.len(), 2);
+    }
+}
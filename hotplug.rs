@@ -0,0 +1,35 @@
+use crate::gamma::connect;
+use eframe::egui;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xproto::Timestamp;
+use x11rb::protocol::Event;
+
+// Waits on RandR in the background and raises the flag whenever an output gets plugged, unplugged or the screen
+// changes, then wakes the gui up so it can re-read. Returns None if X won't talk to us, the gui works without it
+pub fn watch(ctx: egui::Context) -> Option<Arc<AtomicBool>> {
+    let (connection, root) = connect().ok()?;
+    connection.randr_select_input(root, NotifyMask::OUTPUT_CHANGE | NotifyMask::SCREEN_CHANGE).ok()?.check().ok()?;
+    let changed = Arc::new(AtomicBool::new(false));
+    let flag = changed.clone();
+    thread::spawn(move || {
+        while let Ok(event) = connection.wait_for_event() {
+            if matches!(event, Event::RandrNotify(_) | Event::RandrScreenChangeNotify(_)) {
+                flag.store(true, Ordering::Relaxed); // One plug is a burst of events, the gui only needs to look once
+                ctx.request_repaint();
+            }
+        }
+    });
+    Some(changed)
+}
+
+// When the RandR setup was last set by a client and last changed by the hardware. Taken right after brandr's own Apply,
+// the same pair later means the events since then were from that Apply and there's nothing new to read
+pub fn config_times() -> Option<(Timestamp, Timestamp)> {
+    let (connection, root) = connect().ok()?;
+    let resources = connection.randr_get_screen_resources_current(root).ok()?.reply().ok()?;
+    Some((resources.timestamp, resources.config_timestamp))
+}
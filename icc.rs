@@ -0,0 +1,188 @@
+use crate::gamma::{connect, x_error};
+use crate::xrandr::EdidIdentity;
+use crate::{cap, Monitor};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, PropMode};
+use x11rb::wrapper::ConnectionExt as _;
+
+// ICC color profiles from calibrating a monitor. The calibration curve (vcgt tag) goes into the CRTC gamma ramps and
+// the whole file onto _ICC_PROFILE so color managed apps (GIMP, Krita, browsers) pick it up
+
+// Which file goes with which monitor. Kept in config.json by EDID so it follows the monitor to any port
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Assignment {
+    pub edid: EdidIdentity,
+    pub path: PathBuf,
+}
+
+pub struct Profile {
+    pub path: PathBuf,
+    pub description: String, // What the calibration software called it, or the file name
+    pub data: Vec<u8>, // Whole file, what goes on the atom
+    pub vcgt: Option<Vcgt>,
+}
+
+// Video card gamma table. Maps each channel's 0..1 input to what the monitor should get
+pub enum Vcgt {
+    Table([Vec<f64>; 3]),
+    Formula([(f64, f64, f64); 3]), // (gamma, min, max) per channel
+}
+
+impl Vcgt {
+    pub fn apply(&self, channel: usize, level: f64) -> f64 {
+        let level = level.clamp(0.0, 1.0);
+        match self {
+            Vcgt::Table(tables) => {
+                // Tables are usually 256 entries and the CRTC wants 1024 or more, so interpolate
+                let table = &tables[channel];
+                let position = level * (table.len() - 1) as f64;
+                let below = position.floor() as usize;
+                let above = (below + 1).min(table.len() - 1);
+                table[below] + (table[above] - table[below]) * (position - below as f64)
+            }
+            Vcgt::Formula(formulas) => {
+                let (gamma, min, max) = formulas[channel];
+                min + (max - min) * level.powf(gamma)
+            }
+        }
+    }
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        let data = fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        if data.len() < 132 || &data[36..40] != b"acsp" {
+            return Err(format!("{} isn't an ICC profile", path.display()));
+        }
+        let description = tag(&data, b"desc")
+            .and_then(parse_description)
+            .unwrap_or_else(|| path.file_name().unwrap_or_default().to_string_lossy().to_string());
+        let vcgt = tag(&data, b"vcgt").and_then(parse_vcgt);
+        Ok(Profile { path: path.to_path_buf(), description, data, vcgt })
+    }
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// One tag's bytes. The tag table is a count then 12 byte (signature, offset, size) entries right after the 128 byte header
+fn tag<'a>(data: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let count = be_u32(data, 128)? as usize;
+    (0..count).find_map(|n| {
+        let entry = 132 + n * 12;
+        if data.get(entry..entry + 4)? != signature {
+            return None;
+        }
+        let offset = be_u32(data, entry + 4)? as usize;
+        let size = be_u32(data, entry + 8)? as usize;
+        data.get(offset..offset.checked_add(size)?)
+    })
+}
+
+// v2 profiles have a counted ASCII 'desc', v4 a 'mluc' with UTF-16 text per language. The first language is fine
+fn parse_description(tag: &[u8]) -> Option<String> {
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let length = be_u32(tag, 8)? as usize;
+            String::from_utf8_lossy(tag.get(12..12 + length)?).to_string()
+        }
+        b"mluc" => {
+            let length = be_u32(tag, 20)? as usize;
+            let offset = be_u32(tag, 24)? as usize;
+            let units: Vec<u16> = tag.get(offset..offset + length)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+// 'vcgt' then 4 reserved bytes then the type. 0 is a table: channels, entries per channel and entry size (u16 each)
+// then the entries. 1 is a formula: gamma, min and max per channel as 16.16 fixed point
+fn parse_vcgt(tag: &[u8]) -> Option<Vcgt> {
+    match be_u32(tag, 8)? {
+        0 => {
+            let channels = be_u16(tag, 12)? as usize;
+            let entries = be_u16(tag, 14)? as usize;
+            let entry_size = be_u16(tag, 16)? as usize;
+            if !(channels == 1 || channels == 3) || !(entry_size == 1 || entry_size == 2) || entries < 2 {
+                return None;
+            }
+            let table = |channel: usize| -> Option<Vec<f64>> {
+                (0..entries)
+                    .map(|n| {
+                        let at = 18 + (channel * entries + n) * entry_size;
+                        match entry_size {
+                            1 => Some(*tag.get(at)? as f64 / 255.0),
+                            _ => Some(be_u16(tag, at)? as f64 / 65535.0),
+                        }
+                    })
+                    .collect()
+            };
+            if channels == 1 {
+                let shared = table(0)?; // Same curve for all three
+                Some(Vcgt::Table([shared.clone(), shared.clone(), shared]))
+            } else {
+                Some(Vcgt::Table([table(0)?, table(1)?, table(2)?]))
+            }
+        }
+        1 => {
+            let fixed = |at: usize| Some(be_u32(tag, at)? as i32 as f64 / 65536.0);
+            let channel = |n: usize| Some((fixed(12 + n * 12)?, fixed(16 + n * 12)?, fixed(20 + n * 12)?));
+            Some(Vcgt::Formula([channel(0)?, channel(1)?, channel(2)?]))
+        }
+        _ => None,
+    }
+}
+
+// Profile file config.json has for this monitor
+pub fn assigned_path(assignments: &[Assignment], edid: &EdidIdentity) -> Option<PathBuf> {
+    assignments.iter().find(|assignment| assignment.edid == *edid).map(|assignment| assignment.path.clone())
+}
+
+// _ICC_PROFILE for the first monitor, _ICC_PROFILE_1 for the second and so on, in the order X lists monitors (which
+// Xinerama screen numbers follow). Split outputs give each piece the output's profile. Leftovers get cleared
+pub fn set_atoms(monitors: &[Monitor]) -> Result<(), String> {
+    let listed = cap::list_monitors()?;
+    let (connection, root) = connect()?;
+    for index in 0..listed.len().max(8) {
+        let name = match index {
+            0 => String::from("_ICC_PROFILE"),
+            _ => format!("_ICC_PROFILE_{}", index),
+        };
+        let profile = listed
+            .get(index)
+            .and_then(|listed| listed.outputs.first())
+            .and_then(|output| monitors.iter().find(|monitor| monitor.id == *output))
+            .and_then(|monitor| monitor.icc_profile());
+        match profile {
+            Some(profile) => {
+                let atom = connection.intern_atom(false, name.as_bytes()).map_err(x_error)?.reply().map_err(x_error)?.atom;
+                connection
+                    .change_property8(PropMode::REPLACE, root, atom, AtomEnum::CARDINAL, &profile.data)
+                    .map_err(x_error)?
+                    .check()
+                    .map_err(x_error)?;
+            }
+            None => {
+                // Only look the atom up, no point making one just to delete it
+                let atom = connection.intern_atom(true, name.as_bytes()).map_err(x_error)?.reply().map_err(x_error)?.atom;
+                if atom != 0 {
+                    connection.delete_property(root, atom).map_err(x_error)?.check().map_err(x_error)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,18 +2,62 @@ use eframe::{egui, App, epaint::ColorImage, epaint::TextureHandle, egui::Texture
 use image::{DynamicImage, GenericImageView};
 use std::process::Command;
 use std::str;
-use scrap::{Capturer, Display};
 use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 mod cap;  // Import capture module I added in folder
+mod apply;
+mod cli;
+mod config;
+mod ddc;
+mod gamma;
+mod history;
+mod hotplug;
+mod icc;
+mod modeline;
+mod panning;
+mod night_light;
+mod placement;
+mod profile;
+mod providers;
+mod refresh;
+mod virtual_monitor;
+mod vrr;
+mod xrandr;
 struct Monitor {
     id: String,
+    edid: Option<xrandr::EdidIdentity>, // What the monitor says it is. Tells identical connectors apart
     enabled: bool,
-    orientation: String,
-    resolution: (u32, u32),
+    primary: bool,
+    proposed_primary: bool,
+    orientation: String, // xrandr rotation. normal, left, inverted or right
+    proposed_orientation: String,
+    resolution: (u32, u32), // The mode. Unrotated like --mode wants it
     proposed_resolution: Option<(u32, u32)>, // Proposed resolution for the monitor
-    position: egui::Pos2,
-    initial_scaled_position: egui::Pos2,
+    rate: Option<f32>, // Refresh rate it's running at
+    proposed_rate: Option<f32>,
+    modes: Vec<((u32, u32), Vec<f32>)>, // Every mode the output offers with its refresh rates
+    preferred: Option<(u32, u32)>, // Mode xrandr marks with a +. What --auto would pick
+    mode_names: Vec<((u32, u32), f32, String)>, // Modes not just called WxH, like custom ones. --mode only finds those by full name
+    new_modes: Vec<modeline::Modeline>, // Custom modes that get --newmode/--addmode on the next Apply
+    size_mm: (u32, u32), // Physical size. Virtual monitors get a share of it
+    virtual_monitors: Vec<virtual_monitor::VirtualMonitor>, // --setmonitor regions on this output right now
+    proposed_virtual: Vec<virtual_monitor::VirtualMonitor>,
+    panning: panning::Panning, // Area it scrolls around in, if any
+    proposed_panning: panning::Panning,
+    brightness: f32, // Software brightness, 1 is normal. Just scales the gamma ramp
+    proposed_brightness: f32,
+    gamma: [f32; 3], // Red, green, blue like --gamma takes them
+    proposed_gamma: [f32; 3],
+    shown_color: (f32, [f32; 3]), // Brightness and gamma on the screen right now. Sliders preview live before Apply
+    ddc: Option<Result<ddc::Controls, String>>, // Hardware controls once they've been read. Reading is slow so only on request
+    icc: Option<Result<icc::Profile, String>>, // ICC profile attached to this monitor, or why it couldn't be loaded
+    properties: Vec<xrandr::OutputProperty>, // RandR output properties as they are now
+    proposed_properties: Vec<xrandr::OutputProperty>, // Same list with edits. Changed ones get --set on Apply
+    position: egui::Pos2, // Where it sits in real X screen pixels while drawing. Follows proposed_position except mid drag
+    current_position: (i32, i32), // Where it is on the real X screen in pixels
+    proposed_position: (i32, i32), // Where it's going. What ends up in --pos
     scale: f32,
     proposed_status: bool,
     screenshot: Option<DynamicImage>, // can be none with optional
@@ -21,8 +65,170 @@ struct Monitor {
     last_screenshot_time: Instant,
     being_dragged: bool,  // If being dragged dont update the screenshot
     texture: Option<egui::TextureHandle>,
-    scale_x_factor: f32,
-    scale_y_factor: f32
+    texture_privacy: cap::PrivacyMode, // Privacy mode the texture was made with. Redo it right away if that changes
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self {
+            id: String::from(""),
+            edid: None,
+            enabled: true,
+            primary: false,
+            proposed_primary: false,
+            orientation: String::from("normal"),
+            proposed_orientation: String::from("normal"),
+            resolution: (1920, 1080),
+            proposed_resolution: Some((1920, 1080)),
+            rate: None,
+            proposed_rate: None,
+            modes: Vec::new(),
+            preferred: None,
+            mode_names: Vec::new(),
+            new_modes: Vec::new(),
+            size_mm: (0, 0),
+            virtual_monitors: Vec::new(),
+            proposed_virtual: Vec::new(),
+            panning: panning::Panning::default(),
+            proposed_panning: panning::Panning::default(),
+            brightness: 1.0,
+            proposed_brightness: 1.0,
+            gamma: [1.0; 3],
+            proposed_gamma: [1.0; 3],
+            shown_color: (1.0, [1.0; 3]),
+            ddc: None,
+            icc: None,
+            properties: Vec::new(),
+            proposed_properties: Vec::new(),
+            position: egui::Pos2::new(0.0, 0.0),
+            current_position: (0, 0),
+            proposed_position: (0, 0),
+            scale: 1.0,
+            proposed_status: false,
+            screenshot: None,
+            duplicate_of: None,
+            being_dragged: false,
+            last_screenshot_time: Instant::now(),
+            texture: None,
+            texture_privacy: cap::PrivacyMode::Off,
+        }
+    }
+}
+
+impl Monitor {
+    // Size a mode takes up on the X screen. Sideways monitors swap width and height
+    fn rotated(&self, size: (u32, u32)) -> (u32, u32) {
+        rotate_size(&self.orientation, size)
+    }
+
+    fn icc_profile(&self) -> Option<&icc::Profile> {
+        self.icc.as_ref()?.as_ref().ok()
+    }
+
+    // Calibration curve from the ICC profile. Goes into the gamma ramps instead of plain brightness and gamma
+    fn calibration(&self) -> Option<&icc::Vcgt> {
+        self.icc_profile()?.vcgt.as_ref()
+    }
+
+    fn has_changes(&self) -> bool {
+        if self.proposed_status != self.enabled
+            || !self.new_modes.is_empty()
+            || self.proposed_virtual != self.virtual_monitors
+            || self.proposed_properties != self.properties
+        {
+            return true;
+        }
+        self.enabled && (
+            self.proposed_position != self.current_position
+                || self.proposed_resolution.map_or(false, |resolution| resolution != self.resolution)
+                || self.proposed_rate != self.rate
+                || self.proposed_orientation != self.orientation
+                || self.proposed_primary != self.primary
+                || self.proposed_brightness != self.brightness
+                || self.proposed_gamma != self.gamma
+                || self.proposed_panning != self.panning
+        )
+    }
+
+    // Size it will take up once the proposed settings are applied
+    fn screen_size(&self) -> (u32, u32) {
+        rotate_size(&self.proposed_orientation, self.proposed_resolution.unwrap_or(self.resolution))
+    }
+
+    // Mode it gets when it's turned on without picking one. Off outputs have no current mode to go by
+    fn preferred_mode(&self) -> (u32, u32) {
+        self.preferred
+            .or_else(|| self.modes.first().map(|(mode, _)| *mode))
+            .unwrap_or(self.resolution)
+    }
+
+    // Drawn on the canvas if it's on now or about to be. Everything else waits in the disabled tray
+    fn on_canvas(&self) -> bool {
+        self.enabled || self.proposed_status
+    }
+
+    // Size it takes up on the canvas. The current mode while it's on, the one it's going to get otherwise.
+    // In the tray that's the preferred mode, what it lands with when dragged out
+    fn canvas_size(&self) -> (u32, u32) {
+        if self.enabled {
+            self.rotated(self.resolution)
+        } else if self.on_canvas() {
+            self.screen_size()
+        } else {
+            rotate_size(&self.proposed_orientation, self.preferred_mode())
+        }
+    }
+
+    // Throw away every proposed change and go back to what xrandr says it is now
+    fn reset_proposed(&mut self) {
+        self.proposed_status = self.enabled;
+        // Off outputs go back to having no mode so they land in the tray again
+        self.proposed_resolution = if self.enabled { Some(self.resolution) } else { None };
+        self.proposed_rate = self.rate;
+        self.proposed_position = self.current_position;
+        self.proposed_orientation = self.orientation.clone();
+        self.proposed_primary = self.primary;
+        self.proposed_virtual = self.virtual_monitors.clone();
+        self.proposed_panning = self.panning;
+        self.proposed_brightness = self.brightness;
+        self.proposed_gamma = self.gamma;
+        self.proposed_properties = self.properties.clone();
+        // Custom modes that never got created go too
+        for modeline in std::mem::take(&mut self.new_modes) {
+            self.forget_mode(&modeline);
+        }
+    }
+
+    // Take a custom mode that was never created back out of the mode lists
+    fn forget_mode(&mut self, modeline: &modeline::Modeline) {
+        let size = (modeline.hdisplay, modeline.vdisplay);
+        let rate = modeline.refresh() as f32;
+        for (_, rates) in self.modes.iter_mut().filter(|(mode, _)| *mode == size) {
+            rates.retain(|other| *other != rate);
+        }
+        self.modes.retain(|(_, rates)| !rates.is_empty());
+        self.mode_names.retain(|(_, _, name)| *name != modeline.name);
+    }
+
+    // Mode made in the Custom mode window. Shows up like any other mode and gets created on the X server on Apply
+    fn add_custom_mode(&mut self, modeline: modeline::Modeline) {
+        // One waiting for Apply under the same name gets replaced, the timings may have changed
+        if let Some(index) = self.new_modes.iter().position(|pending| pending.name == modeline.name) {
+            let old = self.new_modes.remove(index);
+            self.forget_mode(&old);
+        }
+        if self.mode_names.iter().any(|(_, _, name)| *name == modeline.name) {
+            return; // Already on the output. The dialog checked the timings match
+        }
+        let size = (modeline.hdisplay, modeline.vdisplay);
+        let rate = modeline.refresh() as f32;
+        match self.modes.iter_mut().find(|(mode, _)| *mode == size) {
+            Some((_, rates)) => rates.push(rate),
+            None => self.modes.push((size, vec![rate])),
+        }
+        self.mode_names.push((size, rate, modeline.name.clone()));
+        self.new_modes.push(modeline);
+    }
 }
 
 struct MonitorApp {
@@ -30,10 +236,44 @@ struct MonitorApp {
     selected_monitor: Option<usize>, // Track the selected monitor
     drag_start: Option<usize>,       // Track the monitor being dragged
     screenshot_interval: Duration,
-    net_zero_x: f32,
-    net_zero_y: f32
+    preview_quality: cap::PreviewQuality, // Filter used to shrink screenshots down to the canvas
+    privacy: cap::PrivacyMode, // Blur or hide the previews when screen sharing
+    identify_windows: Vec<(cap::OutputGeometry, String)>, // Where to pop up each identify label and what it says
+    identify_until: Option<Instant>,
+    apply_error: Option<String>, // Why the last Apply didn't take
+    align_target: Option<usize>, // Monitor the align buttons in the settings line things up with
+    history: history::History, // Undo/redo of proposed changes
+    zoom: f32, // Canvas zoom on top of fitting the layout. 1 is fit to view
+    pan: egui::Vec2, // How far the canvas has been moved with the middle button
+    layout_bounds: egui::Rect, // Real X screen rectangle the layout covers. Canvas scale comes from this
+    pending_tabs: i32, // Tab presses kept away from egui's widget focus. Positive is forward, Shift+Tab is back
+    custom_mode: Option<CustomModeDialog>, // Custom mode window if it's open
+    split_count: u32, // How many virtual monitors the Split button makes
+    split_rows: bool, // Stack them instead of side by side
+    night_light: night_light::NightLight, // Settings as they are in the gui
+    night_light_saved: night_light::NightLight, // As they are in config.json
+    night_light_shown: Option<u32>, // Temperature on the screens right now, None for untinted
+    icc_path: String, // What's typed in the ICC profile field
+    hotplug: Option<Arc<AtomicBool>>, // Raised by the RandR watcher when outputs change
+    applied_times: Option<(u32, u32)>, // RandR config times right after brandr's last Apply. Its own events get skipped
+    vrr_driver: Option<(String, bool)>, // Driver and if its VariableRefresh option is on, from the Xorg log
+    providers: Vec<providers::Provider>, // GPUs as RandR sees them. More than one on hybrid laptops
+    show_providers: bool, // Providers window open
+    screen: Option<xrandr::ScreenLimits>, // Smallest and biggest the X screen can be
+    connectors: Vec<xrandr::Connector>, // Every connector, the monitors list only has connected ones
+    turn_off_stale: bool, // Turn off disconnected outputs that are still on when applying
+    framebuffer: Option<(u32, u32)>, // Screen size picked by hand for --fb. None works it out from the layout
+    capture: Option<(x11rb::rust_connection::RustConnection, x11rb::protocol::xproto::Window)>, // X connection the previews are grabbed through, opened on first use
+}
 
-    
+// What's typed into the Custom mode window. Keyed by output id so a refresh can't point it at another monitor
+struct CustomModeDialog {
+    output: String,
+    width: u32,
+    height: u32,
+    refresh: f64,
+    timing: modeline::Timing,
+    error: Option<String>, // Why Add mode didn't take
 }
 
 impl Default for MonitorApp {  // Just defaults for your monitors
@@ -44,47 +284,48 @@ impl Default for MonitorApp {  // Just defaults for your monitors
         Self {
             monitors: vec![
                 Monitor {
-                    id: String::from(""),
-                    enabled: true,
-                    orientation: String::from("Landscape"),
-                    resolution: (1920, 1080),
-                    proposed_resolution: Some((1920,1080)), 
                     position: egui::Pos2::new(50.0, 50.0),
-                    initial_scaled_position: egui::Pos2::new(0.0,0.0),
-                    scale: 1.0,
-                    proposed_status: false,
-                    screenshot: None,
-                    duplicate_of: None,
-                    being_dragged: false,
                     last_screenshot_time: now,
-                    texture: None,
-                    scale_x_factor: 0.0,
-                    scale_y_factor: 0.0,
+                    ..Default::default()
                 },
                 Monitor {
-                    id: String::from(""),
-                    enabled: true,
-                    orientation: String::from("Portrait"),
                     resolution: (1080, 1920),
-                    proposed_resolution: Some((1920,1080)), 
                     position: egui::Pos2::new(300.0, 50.0),
-                    initial_scaled_position: egui::Pos2::new(0.0,0.0),
-                    scale: 1.0,
-                    proposed_status: false,
-                    screenshot: None,
-                    duplicate_of: None,
-                    being_dragged: false,
                     last_screenshot_time: now,
-                    texture: None,
-                    scale_x_factor: 0.0,
-                    scale_y_factor: 0.0,
+                    ..Default::default()
                 },
             ],
             selected_monitor: None,
             drag_start: None,
             screenshot_interval: Duration::from_millis(5000), // can go fast but lags your pc. 1sec might be even fast
-            net_zero_x: 0.0,
-            net_zero_y: 0.0,
+            preview_quality: cap::PreviewQuality::Fast,
+            privacy: cap::PrivacyMode::Off,
+            identify_windows: Vec::new(),
+            identify_until: None,
+            apply_error: None,
+            align_target: None,
+            history: history::History::default(),
+            zoom: 1.0,
+            pan: egui::Vec2::ZERO,
+            layout_bounds: egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1920.0, 1080.0)),
+            pending_tabs: 0,
+            custom_mode: None,
+            split_count: 2,
+            split_rows: false,
+            night_light: night_light::NightLight::default(),
+            night_light_saved: night_light::NightLight::default(),
+            night_light_shown: None,
+            icc_path: String::new(),
+            hotplug: None,
+            applied_times: None,
+            vrr_driver: None,
+            providers: Vec::new(),
+            show_providers: false,
+            screen: None,
+            connectors: Vec::new(),
+            turn_off_stale: false,
+            framebuffer: None,
+            capture: None,
         }
     }
 }
@@ -93,124 +334,119 @@ impl Default for MonitorApp {  // Just defaults for your monitors
 impl MonitorApp {
 
     fn draw_monitors(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
-        // The whole bounding box setup makes this pretty hard. Since you need to offset the rectangles to live in the box. And order them correctly.
-        // If I redid anything it would be the logic here but not too much experience with dynmaic UIs and rust in general
-        let bounding_box = egui::Rect::from_min_size(
-            egui::Pos2::new(100.0, 100.0),
-            egui::vec2(500.0, 300.0),
-        );
-        // Probably going to make this dynamic.
-
-        // Precompute total width and height for scaling purposes
-        let total_monitor_width: f32 = self.monitors.iter().map(|m| m.resolution.0 as f32).sum();
-        let total_monitor_height: f32 = self.monitors.iter().map(|m| m.resolution.1 as f32).sum();
-
-        let total_monitors = self.monitors.len();  // Used to divide by later
-        let scale_factor_x: f32 = (bounding_box.width() / total_monitor_width) * 0.8;  // Scale the monitor to 80% of the bounding box.
-        let scale_factor_y =(bounding_box.height() / total_monitor_height) * 0.8;
-
-
-        
-        let x_position_of_first_mon = (((bounding_box.size().x/scale_factor_x) - total_monitor_width)
-                                                /total_monitors as f32) + (bounding_box.min.x/scale_factor_x);
-        self.net_zero_x = x_position_of_first_mon; // Saved to use to check how far the distance is from normal?
-        // Insanity, Take the real total mon width and subtract the space we would have in the bounding box if it was scaled up(we cannot use regular since it is used to scale, division reverses multiplication lol)
-        // That gives you total space for monitors within the bounding box with some padding.
-        // Divide the by the number of monitors as the outcome should give you the amount of padding to start with and end with
-        // Then add the scaled bounding box starting x point since it starts somewhere and without it we would be starting on the edge of the screen.
-        let mut x_position_of_next_mon = 0.0;  // If the monitor is not the first then we need to hold the position of the second monitor if there is a third
-        // Since really the monitor in the corner could be any
-
-        let mut first_mon_height = 0;
-
-        if let Some(first_monitor) = self.monitors.first() {
-            first_mon_height = first_monitor.resolution.1;  // Access height without owning
-        }
-        let y_position_of_first_mon = ((bounding_box.height()/scale_factor_y)/2.0 - (first_mon_height as f32 /2.0)) + (bounding_box.min.y/scale_factor_y);
-        self.net_zero_y = y_position_of_first_mon;
-        // More insanity but i call in minsanity
-        // Get the height of the first mon. Hopefully even lol but will use first as the guide. 
-        // divide the mon height by 2 to get the midpoint. 
-        // Take the bounding box height divide by two to get the midpoint. 
-        // box should be bigger than mon height subtract them to give you where the monitor height should start.
-        // Then add the offset of where the boundingbox actually starts getting drawn. Same scale divides as x. This ones easiers though since all mons can use same y
-        // All just used to center the monitors in the box.
-
+        // Canvas takes the full width and most of the height so it grows and shrinks with the window.
+        // Rest of the height is left for the disabled tray and the settings underneath
+        let tray_height = if self.monitors.iter().any(|monitor| !monitor.on_canvas()) { 70.0 } else { 0.0 };
+        let canvas_size = egui::vec2(ui.available_width(), (ui.available_height() * 0.6 - tray_height).max(200.0));
+        let (canvas_rect, canvas_response) = ui.allocate_exact_size(canvas_size, egui::Sense::click());
         ui.painter().rect_stroke(
-            bounding_box,
+            canvas_rect,
             0.0,
             egui::Stroke::new(1.0, egui::Color32::LIGHT_GRAY),
-        ); // Painting of the bounding box. 
+        ); // Painting of the canvas border
+
+        // Turned on from the settings or the keyboard instead of dragged out of the tray. No mode or spot picked yet
+        // so give it the preferred mode and put it just right of everything else. Done first so it counts toward the layout bounds
+        let right_edge = self.monitors.iter()
+            .filter(|monitor| monitor.on_canvas() && monitor.proposed_resolution.is_some())
+            .map(|monitor| monitor.proposed_position.0 + monitor.canvas_size().0 as i32)
+            .max()
+            .unwrap_or(0); // Default to 0 if nothing is on the canvas yet
+        let top_edge = self.monitors.iter()
+            .filter(|monitor| monitor.on_canvas() && monitor.proposed_resolution.is_some())
+            .map(|monitor| monitor.proposed_position.1)
+            .min()
+            .unwrap_or(0);
+        for monitor in self.monitors.iter_mut().filter(|monitor| monitor.on_canvas() && monitor.proposed_resolution.is_none()) {
+            monitor.proposed_resolution = Some(monitor.preferred_mode());
+            monitor.proposed_position = (right_edge, top_edge);
+        }
 
-        let displays = scrap::Display::all().unwrap();  // Pull the the displays from scrap should be all that are connected
-        let displays_size = displays.len();
-        let mut wasMoved = 0;
-        for (i, display) in displays.into_iter().enumerate() {
+        // Mouse wheel zooms around the pointer, middle button pans. Only while the mouse is over the canvas
+        if let Some(pointer) = ui.input(|i| i.pointer.hover_pos()).filter(|pointer| canvas_rect.contains(*pointer)) {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                let zoom = (self.zoom * (scroll * 0.0015).exp()).clamp(0.1, 20.0);
+                // Keep whatever is under the pointer under the pointer
+                let from_center = pointer - canvas_rect.center();
+                self.pan = from_center - (from_center - self.pan) * (zoom / self.zoom);
+                self.zoom = zoom;
+            }
+            if ui.input(|i| i.pointer.middle_down()) {
+                self.pan += ui.input(|i| i.pointer.delta());
+            }
+        }
+        if canvas_response.double_clicked() {
+            self.fit_to_view();
+        }
+
+        // Scale comes from the real rectangle the layout covers on the X screen, so it fits no matter how monitors are arranged.
+        // Left alone mid drag so the layout doesn't slide around under the mouse as its bounds change
+        let any_dragged = self.monitors.iter().any(|monitor| monitor.being_dragged);
+        let placed: Vec<&Monitor> = self.monitors.iter().filter(|monitor| monitor.on_canvas()).collect();
+        if !any_dragged && !placed.is_empty() {
+            let min_x = placed.iter().map(|m| m.proposed_position.0).min().unwrap_or(0) as f32;
+            let min_y = placed.iter().map(|m| m.proposed_position.1).min().unwrap_or(0) as f32;
+            let max_x = placed.iter().map(|m| m.proposed_position.0 + m.canvas_size().0 as i32).max().unwrap_or(0) as f32;
+            let max_y = placed.iter().map(|m| m.proposed_position.1 + m.canvas_size().1 as i32).max().unwrap_or(0) as f32;
+            self.layout_bounds = egui::Rect::from_min_max(egui::pos2(min_x, min_y), egui::pos2(max_x, max_y));
+        }
+        let bounds = self.layout_bounds;
+        let scale = (canvas_rect.width() / bounds.width().max(1.0))
+            .min(canvas_rect.height() / bounds.height().max(1.0)) * 0.8 * self.zoom; // Fill 80% of the canvas at zoom 1
+        // Where real 0,0 lands on the canvas. Layout is centered then panned
+        let origin = canvas_rect.center() + self.pan - bounds.center().to_vec2() * scale;
+
+        // Zoomed in monitors can hang off the canvas. Don't draw over the settings
+        let previous_clip = ui.clip_rect();
+        ui.set_clip_rect(canvas_rect.intersect(previous_clip));
+
+        // Picked from a monitor's right click menu. (monitor, relation, alignment, other monitor). Done after the loop so it can touch both
+        let mut placement_request: Option<(usize, placement::Relation, placement::Align, usize)> = None;
+        let enabled_ids: Vec<(usize, String)> = self.monitors.iter()
+            .enumerate()
+            .filter(|(_, monitor)| monitor.on_canvas())
+            .map(|(idx, monitor)| (idx, monitor.id.clone()))
+            .collect();
+        for i in 0..self.monitors.len() {
             let any_active = {
                 self.monitors.iter_mut().any(|monitor| monitor.being_dragged)
             };// Rust not letting you borrow something more than once.. So use it to set a var then we can release it for the mut to get referenced again
 
-            let mut monitor = &mut self.monitors[i+wasMoved]; // I think I take ownership of monitors here. 
-            //dbg!(&monitor.id);
-            if monitor.enabled == false {
-                monitor = &mut self.monitors[i+1+wasMoved];
-                wasMoved += 1;
-                //dbg!(&monitor.id);
-                //dbg!(wasMoved);
+            let monitor = &mut self.monitors[i];
+            if !monitor.on_canvas() {
+                continue; // Disabled mons get drawn in the tray
             }
-            //dbg!("Pre Scaled Position");
-            //dbg!(monitor.position);
-            
-            // The initial position needs to be added by the bounding box to be put in there as well
-            // So on spawn adjust their position to be in the box.
-            
-            if monitor.texture == None {
-                //dbg!("No texture");
-                // if first monitor set position to center of bounding box and monitor offsets
-                if monitor.position.x == 0.0 {  // First monitor
-                    monitor.position.x = x_position_of_first_mon;
-                    
-                    monitor.position.y = y_position_of_first_mon;
-                    monitor.initial_scaled_position = egui::Pos2::new(monitor.position.x * scale_factor_x,monitor.position.y * scale_factor_y);
-                    // To check after if the monitors have been moved by the user later
-                }
-                // other monitors if x add them together to get correct spacing
-                // y's all can stay the same.
-                else{
-                    // You could just be a new mon enabled without a screenshot.. This should really just be inital run. How can I say receently enabled. Eh I can just apply a default texture
-                    //dbg!("not first mon");
-                    monitor.position.x = x_position_of_first_mon + x_position_of_next_mon + (total_monitor_width/total_monitors as f32);
-                    // Take the position where the first monitor started and a the size of the monitor. Should give us perfect aligntment
-                    // And add the position of the next mon incase there is a third. 
-                    x_position_of_next_mon = monitor.position.x;  // Incase you have more monitors keep it going. and save to seperate var incase the second mon was first in the output
-
-                    monitor.position.y = y_position_of_first_mon; // all mons can use same y no need to change.
-                    monitor.initial_scaled_position = egui::Pos2::new(monitor.position.x * scale_factor_x,monitor.position.y * scale_factor_y);
-                    // to check after initial position if monitors have been moved by the user later.
-                }
-
-                // Set the scaled factor so we can undo it later to get the position.. Should change on new run
-                monitor.scale_x_factor = scale_factor_x;
-                monitor.scale_y_factor = scale_factor_y;
-
-            } 
-            
-            
-            let scaled_position = egui::Pos2::new(
-                monitor.position.x * scale_factor_x,
-                monitor.position.y * scale_factor_y,
-                
-            );
-            // Forces me a bit to divide by the scale factor for this to all work correctly. to center things. Cannot just use real unscaled numbers to set the position since it would get multiplied by the scale factor and break the proportions.   
+            // Where it is on the X screen right now, so the capture can cut it out. Nothing to capture until it's on
+            let area = monitor.enabled.then(|| (monitor.current_position, monitor.rotated(monitor.resolution)));
+            // Follow the real position unless it's being dragged, then the drag is in charge
+            if !monitor.being_dragged {
+                monitor.position = egui::Pos2::new(monitor.proposed_position.0 as f32, monitor.proposed_position.1 as f32);
+            }
+
+            let scaled_position = origin + monitor.position.to_vec2() * scale;
             let scaled_size = egui::vec2(
-                monitor.resolution.0 as f32 * scale_factor_x,
-                monitor.resolution.1 as f32 * scale_factor_y,
+                monitor.canvas_size().0 as f32 * scale,
+                monitor.canvas_size().1 as f32 * scale,
             );
-            //dbg!(monitor.position);
 
             // Check if it's time to update the screenshot. If there is no texture we need. One. Really we need a default texture but will live.
-            if (monitor.last_screenshot_time.elapsed() > self.screenshot_interval && (any_active == false)) || monitor.texture == None {
-                if let Some(screenshot) = cap::capture_screen(display) {
+            let privacy = self.privacy;
+            if (monitor.last_screenshot_time.elapsed() > self.screenshot_interval && (any_active == false)) || monitor.texture == None || monitor.texture_privacy != privacy {
+                let display_missing = area.is_none();
+                // Capture at the size it is drawn at (in real pixels for hidpi) instead of the full screen
+                let target_size = (
+                    (scaled_size.x * ctx.pixels_per_point()).round() as u32,
+                    (scaled_size.y * ctx.pixels_per_point()).round() as u32,
+                );
+                let preview_quality = self.preview_quality;
+                if self.capture.is_none() && area.is_some() && privacy != cap::PrivacyMode::Hide {
+                    self.capture = gamma::connect().ok();
+                }
+                let screenshot = area.zip(self.capture.as_ref()).and_then(|((position, size), (connection, root))| {
+                    cap::capture_screen(connection, *root, position, size, target_size, preview_quality, privacy)
+                });
+                if let Some(screenshot) = screenshot {
                     monitor.last_screenshot_time = Instant::now();  // Reset the timer
 
                     // Convert the new screenshot to a texture
@@ -220,12 +456,29 @@ impl MonitorApp {
                         &screenshot.to_rgba(),
                     );
 
-                    // Load the new texture
-                    monitor.texture = Some(ctx.load_texture(
-                        "monitor_screenshot",
-                        color_image,
-                        TextureOptions::default(),
-                    ));
+                    // Reuse the texture we already have instead of allocating a new one every refresh
+                    if let Some(texture) = &mut monitor.texture {
+                        texture.set(color_image, TextureOptions::default());
+                    } else {
+                        monitor.texture = Some(ctx.load_texture(
+                            "monitor_screenshot",
+                            color_image,
+                            TextureOptions::default(),
+                        ));
+                    }
+                    monitor.texture_privacy = privacy;
+                } else if privacy == cap::PrivacyMode::Hide || monitor.texture_privacy != privacy || (display_missing && monitor.texture == None) {
+                    // Hidden or not on yet so there is nothing to capture. Gray it out so it still gets drawn.
+                    // Same if the privacy mode changed and the capture failed, the old frame was taken under the looser one
+                    let gray_image = vec![128u8; 10 * 10 * 4];
+                    let color_image = ColorImage::from_rgba_unmultiplied([10, 10], &gray_image);
+                    if let Some(texture) = &mut monitor.texture {
+                        texture.set(color_image, TextureOptions::default());
+                    } else {
+                        monitor.texture = Some(ctx.load_texture("gray_texture", color_image, TextureOptions::default()));
+                    }
+                    monitor.last_screenshot_time = Instant::now();
+                    monitor.texture_privacy = privacy;
                 }
             }
             
@@ -237,146 +490,589 @@ impl MonitorApp {
                     scaled_size,
                     );
                 let response = ui.allocate_rect(monitor_rect, egui::Sense::click_and_drag());
-                
-                // Keep monitors inside the bounding box and adjust positions if needed           
-                if monitor_rect.min.x < bounding_box.min.x {
-                    monitor.position.x = bounding_box.min.x / scale_factor_x;
-                }
-                if monitor_rect.max.x > bounding_box.max.x {
-                    monitor.position.x = (bounding_box.max.x - scaled_size.x) / scale_factor_x;
-                }
-                if monitor_rect.min.y < bounding_box.min.y {
-                    monitor.position.y = bounding_box.min.y / scale_factor_y;
-                }
-                if monitor_rect.max.y > bounding_box.max.y {
-                    monitor.position.y = (bounding_box.max.y - scaled_size.y) / scale_factor_y;
-                }
-                
-                
-                
 
-                if response.dragged() { // Move the monitor by the drag delta with the scale factor
-                    monitor.position += response.drag_delta() / egui::Vec2::new(scale_factor_x, scale_factor_y);
+                // Only the left button moves monitors, the middle one pans the canvas
+                if response.dragged_by(egui::PointerButton::Primary) {
+                    // Drag happens in real pixels so it works the same at any zoom
+                    monitor.position += response.drag_delta() / scale;
+                    monitor.proposed_position = (monitor.position.x.round() as i32, monitor.position.y.round() as i32);
                 }
                         // Check if dragging and set flag
-                if response.drag_started() {
+                if response.drag_started_by(egui::PointerButton::Primary) {
                     monitor.being_dragged = true;  // Used for saying when to take a screenshot or not so we don't update when you are touching the screen
                 }
-                if response.drag_released() {
+                if response.drag_stopped() {
                     monitor.being_dragged = false; // Still some lag when clicking but helps a lot and don't have to thread
                 }
 
 
                 // When clicked select the monitor
                 if response.clicked() {
-                    self.selected_monitor = Some(i+wasMoved);
+                    self.selected_monitor = Some(i);
                 }
-                
-            
+
+                // Right click to put it against another monitor exactly instead of eyeballing a drag
+                response.context_menu(|ui| {
+                    for relation in placement::Relation::ALL {
+                        ui.menu_button(relation.label(), |ui| {
+                            for (other, other_id) in enabled_ids.iter().filter(|(other, _)| *other != i) {
+                                ui.menu_button(other_id, |ui| {
+                                    for align in placement::Align::ALL {
+                                        if ui.button(align.label(relation)).clicked() {
+                                            placement_request = Some((i, relation, align, *other));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            }
+                        });
+                    }
+                });
                 
                 // Paint the screenshot within the monitor rectangle
                 egui::Image::new(texture)
                     .rounding(5.0)
                     .tint(egui::Color32::WHITE)
                     .paint_at(ui, monitor_rect);
+                paint_label(ui, monitor_rect, monitor_label(monitor));
+
+                // Virtual monitors on top of their output. Lines between them can be dragged on the selected one
+                let region_rect = |region: &virtual_monitor::VirtualMonitor| egui::Rect::from_min_size(
+                    monitor_rect.min + egui::vec2(region.x as f32, region.y as f32) * scale,
+                    egui::vec2(region.width as f32, region.height as f32) * scale,
+                );
+                for region in &monitor.proposed_virtual {
+                    let rect = region_rect(region);
+                    ui.painter().rect_stroke(rect.shrink(1.0), 0.0, egui::Stroke::new(1.0, egui::Color32::YELLOW));
+                    ui.painter().text(
+                        rect.left_top() + egui::vec2(4.0, 4.0),
+                        egui::Align2::LEFT_TOP,
+                        &region.name,
+                        egui::TextStyle::Small.resolve(ui.style()),
+                        egui::Color32::YELLOW,
+                    );
+                }
+                if self.selected_monitor == Some(i) {
+                    for (first, second, vertical) in virtual_monitor::dividers(&monitor.proposed_virtual) {
+                        let first_rect = region_rect(&monitor.proposed_virtual[first]);
+                        let line = if vertical {
+                            egui::Rect::from_center_size(first_rect.right_center(), egui::vec2(6.0, first_rect.height()))
+                        } else {
+                            egui::Rect::from_center_size(first_rect.center_bottom(), egui::vec2(first_rect.width(), 6.0))
+                        };
+                        let divider = ui.interact(line, egui::Id::new(("split_divider", &monitor.id, first, second)), egui::Sense::drag());
+                        if divider.hovered() || divider.dragged() {
+                            ui.ctx().set_cursor_icon(if vertical { egui::CursorIcon::ResizeHorizontal } else { egui::CursorIcon::ResizeVertical });
+                        }
+                        if let Some(pointer) = divider.interact_pointer_pos().filter(|_| divider.dragged()) {
+                            // Where the pointer is in real pixels from the output's corner
+                            let at = if vertical { (pointer.x - monitor_rect.min.x) / scale } else { (pointer.y - monitor_rect.min.y) / scale };
+                            virtual_monitor::move_divider(&mut monitor.proposed_virtual, first, second, vertical, at.round() as i32);
+                        }
+                    }
+                }
+
+                // Outline the selected one so you can see where Tab and the arrow keys are
+                if self.selected_monitor == Some(i) {
+                    ui.painter().rect_stroke(monitor_rect, 5.0, ui.visuals().selection.stroke);
+                }
             }
         }
         
 
-        // Render disabled displays
-        // Second loop to render blank monitors that were not in scrap
-        if self.monitors.len() - displays_size != 0 { 
-            let max_x_position = self.monitors.iter()
-                .filter(|monitor| monitor.enabled) // Only include enabled monitors
-                .map(|monitor| monitor.position.x)
-                .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                .unwrap_or(0.0); // Default to 0.0 if no enabled monitors found
-            // This is used because I need to know where the last monitor was. This breaks down though with multiple disabled monitors. Lots of bugs with this approach but shoudl work. 
-
-            // There is a disabled mon
-            for i in 0..self.monitors.len() {
-                let mon = &mut self.monitors[i];
-
-                if mon.enabled == false{
-                    //dbg!(x_position_of_next_mon); // Math this tomorrow somethings wrong here not scaling to three mons if was empty or not. better to look above since I think the issue is there. debug by running quickly to see what this is at.
-                    mon.position.x = max_x_position + (total_monitor_width/total_monitors as f32);
-                    // Take the position where the first monitor started and a the size of the monitor. Should give us perfect aligntment
-                    // And add the position of the next mon incase there is a third. 
-                    //x_position_of_next_mon = mon.position.x;  // Incase you have more monitors keep it going. and save to seperate var incase the second mon was first in the output
-                    mon.position.y = y_position_of_first_mon; // all mons can use same y no need to change.
-                    mon.initial_scaled_position = egui::Pos2::new(mon.position.x * scale_factor_x,mon.position.y * scale_factor_y);
-                    // to check after initial position if monitors have been moved by the user later.
-
-                    let scaled_position = egui::Pos2::new(
-                        mon.position.x * scale_factor_x,
-                        mon.position.y * scale_factor_y,
-                    );
+        if let Some((index, relation, align, other)) = placement_request {
+            let _ = placement::place(&mut self.monitors, index, relation, align, other); // Menu never offers the monitor itself
+            self.selected_monitor = Some(index);
+        }
 
-                    let scaled_size = egui::vec2(
-                        mon.resolution.0 as f32 * scale_factor_x,
-                        mon.resolution.1 as f32 * scale_factor_y,
-                    );
-                    let monitor_rect = egui::Rect::from_min_size(scaled_position, scaled_size);
-                    
-                    let response = ui.allocate_rect(monitor_rect, egui::Sense::click_and_drag());
-                        
-                        // Keep monitors inside the bounding box and adjust positions if needed           
-                        if monitor_rect.min.x < bounding_box.min.x {
-                            mon.position.x = bounding_box.min.x / scale_factor_x;
-                        }
-                        if monitor_rect.max.x > bounding_box.max.x {
-                            mon.position.x = (bounding_box.max.x - scaled_size.x) / scale_factor_x;
-                        }
-                        if monitor_rect.min.y < bounding_box.min.y {
-                            mon.position.y = bounding_box.min.y / scale_factor_y;
-                        }
-                        if monitor_rect.max.y > bounding_box.max.y {
-                            mon.position.y = (bounding_box.max.y - scaled_size.y) / scale_factor_y;
-                        }
-                        
-                        
-                        
+        ui.set_clip_rect(previous_clip);
 
-                        if response.dragged() { // Move the monitor by the drag delta with the scale factor
-                            mon.position += response.drag_delta() / egui::Vec2::new(scale_factor_x, scale_factor_y);
-                        }
-                                // Check if dragging and set flag
-                        if response.drag_started() {
-                            mon.being_dragged = true;  // Used for saying when to take a screenshot or not so we don't update when you are touching the screen
-                        }
-                        if response.drag_released() {
-                            mon.being_dragged = false; // Still some lag when clicking but helps a lot and don't have to thread
-                        }
-                        // When clicked select the monitor
-                        if response.clicked() {
-                            self.selected_monitor = Some(i);
-                        }
-                    // Render blank monitor with a gray fill
-                    let gray_image = vec![128u8; 10 * 10 * 4]; // RGBA values, fully gray
-                    let color_image = egui::ColorImage::from_rgba_unmultiplied([10, 10], &gray_image);
-                    mon.texture = Some(ctx.load_texture("gray_texture", color_image, egui::TextureOptions::default()));
-                    ui.painter().rect_filled(monitor_rect, 0.0, egui::Color32::from_gray(50));
+        if tray_height > 0.0 {
+            self.draw_disabled_tray(ui, tray_height, canvas_rect, origin, scale);
+        }
+    }
+
+    // Outputs that are off and staying off sit here side by side at their preferred mode size.
+    // Drag one onto the canvas to turn it on where it's dropped
+    fn draw_disabled_tray(&mut self, ui: &mut egui::Ui, tray_height: f32, canvas_rect: egui::Rect, origin: egui::Pos2, scale: f32) {
+        let (tray_rect, _) = ui.allocate_exact_size(egui::vec2(canvas_rect.width(), tray_height), egui::Sense::hover());
+        ui.painter().rect_filled(tray_rect, 0.0, ui.visuals().extreme_bg_color);
+        ui.painter().text(
+            tray_rect.right_top() + egui::vec2(-4.0, 2.0),
+            egui::Align2::RIGHT_TOP,
+            "Disabled - drag onto the canvas to enable",
+            egui::TextStyle::Small.resolve(ui.style()),
+            ui.visuals().weak_text_color(),
+        );
+
+        // Same scale for all of them so they stay in proportion, small enough that the tallest fits
+        let padding = 8.0;
+        let tallest = self.monitors.iter()
+            .filter(|monitor| !monitor.on_canvas())
+            .map(|monitor| monitor.canvas_size().1)
+            .max()
+            .unwrap_or(1) as f32;
+        let tray_scale = scale.min((tray_height - padding * 2.0) / tallest);
+
+        let previous_clip = ui.clip_rect();
+        ui.set_clip_rect(tray_rect.intersect(previous_clip));
+        let mut x = tray_rect.min.x + padding;
+        let mut dropped: Option<(usize, (i32, i32))> = None;
+        for i in 0..self.monitors.len() {
+            let monitor = &mut self.monitors[i];
+            if monitor.on_canvas() {
+                continue;
+            }
+            let (width, height) = monitor.canvas_size();
+            let size = egui::vec2(width as f32 * tray_scale, height as f32 * tray_scale);
+            let monitor_rect = egui::Rect::from_min_size(egui::pos2(x, tray_rect.center().y - size.y / 2.0), size);
+            x += size.x + padding;
+
+            // Own id so the drag survives other tray entries coming and going
+            let response = ui.interact(monitor_rect, egui::Id::new(("disabled_tray", &monitor.id)), egui::Sense::click_and_drag());
+            if response.clicked() {
+                self.selected_monitor = Some(i);
+            }
+            if response.drag_started_by(egui::PointerButton::Primary) {
+                monitor.being_dragged = true; // Keeps the canvas scale still so it drops where it's shown
+            }
+
+            // Ghost at canvas scale under the pointer while dragging. On top of everything so it isn't clipped to the tray
+            let pointer = ui.input(|i| i.pointer.latest_pos());
+            let ghost = pointer.map(|pointer| egui::Rect::from_center_size(
+                pointer,
+                egui::vec2(width as f32 * scale, height as f32 * scale),
+            ));
+            if monitor.being_dragged {
+                if let Some(ghost) = ghost {
+                    let painter = ui.ctx().layer_painter(egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("disabled_tray_ghost")));
+                    painter.rect_filled(ghost, 0.0, egui::Color32::from_gray(50).gamma_multiply(0.8));
+                    painter.rect_stroke(ghost, 0.0, ui.visuals().selection.stroke);
+                }
+            }
+            if response.drag_stopped() && monitor.being_dragged {
+                monitor.being_dragged = false;
+                if let Some(ghost) = ghost.filter(|ghost| canvas_rect.contains(ghost.center())) {
+                    // Back to real X screen pixels from where the ghost's corner is
+                    let position = (ghost.min - origin) / scale;
+                    dropped = Some((i, (position.x.round() as i32, position.y.round() as i32)));
+                }
+            }
+
+            // Render blank monitor with a gray fill
+            ui.painter().rect_filled(monitor_rect, 0.0, egui::Color32::from_gray(50));
+            paint_label(ui, monitor_rect, monitor_label(monitor));
+            if self.selected_monitor == Some(i) {
+                ui.painter().rect_stroke(monitor_rect, 0.0, ui.visuals().selection.stroke);
+            }
+        }
+        ui.set_clip_rect(previous_clip);
+
+        if let Some((index, position)) = dropped {
+            let monitor = &mut self.monitors[index];
+            monitor.proposed_status = true;
+            monitor.proposed_resolution = Some(monitor.preferred_mode());
+            monitor.proposed_position = position;
+            self.selected_monitor = Some(index);
+        }
+    }
+
+    // Arrange without a mouse. Tab picks the monitor, arrows move it 1px, Shift 10px, Ctrl to the next edge.
+    // Space turns it on/off, P makes it primary and Enter applies
+    fn handle_layout_keys(&mut self, ctx: &egui::Context) {
+        let count = self.monitors.len() as i32;
+        if self.pending_tabs != 0 && count > 0 {
+            let current = match self.selected_monitor {
+                Some(selected) => selected as i32,
+                None if self.pending_tabs > 0 => -1, // First Tab lands on the first monitor, Shift+Tab on the last
+                None => 0,
+            };
+            self.selected_monitor = Some((current + self.pending_tabs).rem_euclid(count) as usize);
+        }
+        self.pending_tabs = 0;
+
+        if let Some(selected) = self.selected_monitor {
+            for (key, axis, forward) in [
+                (egui::Key::ArrowLeft, placement::Axis::Horizontal, false),
+                (egui::Key::ArrowRight, placement::Axis::Horizontal, true),
+                (egui::Key::ArrowUp, placement::Axis::Vertical, false),
+                (egui::Key::ArrowDown, placement::Axis::Vertical, true),
+            ] {
+                // Ctrl and Shift first, a plain pattern matches with modifiers held too
+                let distance = if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, key)) {
+                    placement::next_edge(&self.monitors, selected, axis, forward)
+                } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::SHIFT, key)) {
+                    Some(if forward { 10 } else { -10 })
+                } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key)) {
+                    Some(if forward { 1 } else { -1 })
+                } else {
+                    None
+                };
+                let monitor = &mut self.monitors[selected];
+                if let (Some(distance), true) = (distance, monitor.on_canvas()) { // Nothing to move while it sits in the tray
+                    match axis {
+                        placement::Axis::Horizontal => monitor.proposed_position.0 += distance,
+                        placement::Axis::Vertical => monitor.proposed_position.1 += distance,
                     }
+                }
+            }
+
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Space)) {
+                let monitor = &mut self.monitors[selected];
+                monitor.proposed_status = !monitor.proposed_status;
+            }
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::P)) {
+                // Only one primary
+                for (idx, monitor) in self.monitors.iter_mut().enumerate() {
+                    monitor.proposed_primary = idx == selected;
+                }
             }
         }
-        
-        
 
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter))
+            && self.monitors.iter().any(|monitor| monitor.has_changes())
+        {
+            self.apply_layout();
+        }
+    }
 
-        
-        // Dynamically set the cursor position to the bottom of the bounding box
-        let new_ui_position = egui::Pos2::new(bounding_box.min.x, bounding_box.max.y + 10.0);
-        ui.allocate_ui_at_rect(
-            egui::Rect::from_min_size(new_ui_position, egui::vec2(500.0, 30.0)), 
-            |ui| {
-                ui.separator(); // Add the separator at the specified position
+    // Apply everything. The X screen size changed with it, so keep that up to date for the Screen size row
+    fn apply_layout(&mut self) {
+        // Nothing gets turned off for a layout that's going to be refused anyway
+        if let Err(e) = apply::check_screen_size(&self.monitors, self.framebuffer, self.screen.as_ref()) {
+            self.apply_error = Some(e);
+            return;
+        }
+        if self.turn_off_stale {
+            let stale: Vec<String> = self.connectors.iter().filter(|connector| connector.is_stale()).map(|connector| connector.id.clone()).collect();
+            if let Err(e) = apply::turn_off(&stale) {
+                self.apply_error = Some(e);
+                return;
             }
-        );
+            self.connectors = xrandr::get_connectors();
+        }
+        self.night_light_shown = self.night_light.active_temperature();
+        self.apply_error = apply::apply_monitors(&mut self.monitors, self.framebuffer, self.screen.as_ref(), self.night_light_shown).err();
+        self.applied_times = hotplug::config_times();
+        if self.apply_error.is_none() {
+            self.history.rebase(&self.monitors); // Apply lines positions up, that isn't an edit
+            if let Some(screen) = &mut self.screen {
+                screen.current = self.framebuffer.unwrap_or(apply::framebuffer_size(&self.monitors));
+            }
+        }
+    }
+
+    // Tint the screens when the night light settings or the schedule say so, and take it off again when they don't
+    fn update_night_light(&mut self, ctx: &egui::Context) {
+        let wanted = self.night_light.active_temperature();
+        if wanted != self.night_light_shown {
+            if let Err(e) = gamma::set_ramps(&self.monitors, wanted) {
+                self.apply_error = Some(e);
+            }
+            self.night_light_shown = wanted;
+        }
+        if self.night_light.enabled && self.night_light.scheduled {
+            ctx.request_repaint_after(Duration::from_secs(30)); // Keep checking the clock even when nothing moves
+        }
+    }
+
+    // Back to the whole layout centered in the canvas
+    fn fit_to_view(&mut self) {
+        self.zoom = 1.0;
+        self.pan = egui::Vec2::ZERO;
     }
     
     
+    // Each GPU, what it can do and linking them. Linking changes which outputs exist so it re-reads everything right after
+    fn draw_providers_window(&mut self, ctx: &egui::Context) {
+        if !self.show_providers {
+            return;
+        }
+        let mut link: Option<Result<(), String>> = None;
+        let providers = &self.providers;
+        egui::Window::new("Providers")
+            .open(&mut self.show_providers)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("providers_grid").num_columns(5).striped(true).show(ui, |ui| {
+                    for heading in ["Provider", "Can", "CRTCs", "Outputs", "Linked with"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+                    for provider in providers {
+                        ui.label(provider.label());
+                        ui.label(provider.capabilities.join(", "));
+                        ui.label(provider.crtcs.to_string());
+                        ui.label(provider.outputs.to_string());
+                        ui.label(provider.associated.to_string());
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+
+                for provider in providers {
+                    let others = || providers.iter().filter(move |other| other.id != provider.id);
+                    if provider.can(providers::SINK_OUTPUT) && provider.outputs > 0 {
+                        ui.horizontal(|ui| {
+                            ui.menu_button(format!("Show on {}'s outputs", provider.label()), |ui| {
+                                for source in others().filter(|other| other.can(providers::SOURCE_OUTPUT)) {
+                                    if ui.button(format!("What {} renders", source.label())).clicked() {
+                                        link = Some(providers::set_output_source(&provider.id, Some(&source.id)));
+                                        ui.close_menu();
+                                    }
+                                }
+                                if ui.button("Nothing (unlink)").clicked() {
+                                    link = Some(providers::set_output_source(&provider.id, None));
+                                    ui.close_menu();
+                                }
+                            });
+                            if provider.associated == 0 {
+                                ui.label("Its outputs stay hidden until it's linked");
+                            }
+                        });
+                    }
+                    if provider.can(providers::SOURCE_OFFLOAD) {
+                        ui.menu_button(format!("Offload rendering from {}", provider.label()), |ui| {
+                            for sink in others().filter(|other| other.can(providers::SINK_OFFLOAD)) {
+                                if ui.button(format!("Shown by {}", sink.label())).clicked() {
+                                    link = Some(providers::set_offload_sink(&provider.id, Some(&sink.id)));
+                                    ui.close_menu();
+                                }
+                            }
+                            if ui.button("Nowhere (unlink)").clicked() {
+                                link = Some(providers::set_offload_sink(&provider.id, None));
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                }
+            });
+
+        if let Some(result) = link {
+            self.apply_error = result.err();
+            self.refresh();
+        }
+    }
+
+    // Make a mode the monitor doesn't list. Timings are worked out here, the mode gets created on Apply
+    fn draw_custom_mode_dialog(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.custom_mode else { return };
+        let mut open = true;
+        let mut added: Option<modeline::Modeline> = None;
+        egui::Window::new(format!("Custom mode for {}", dialog.output))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut dialog.width).range(320..=16384));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut dialog.height).range(200..=16384));
+                    ui.label("@");
+                    ui.add(egui::DragValue::new(&mut dialog.refresh).range(1.0..=500.0).speed(0.1).suffix(" Hz"));
+                    egui::ComboBox::from_id_source("custom_mode_timing_combobox")
+                        .selected_text(dialog.timing.label())
+                        .show_ui(ui, |ui| {
+                            for timing in modeline::Timing::ALL {
+                                ui.selectable_value(&mut dialog.timing, timing, timing.label());
+                            }
+                        });
+                });
+
+                // Preview of what the monitor is going to get
+                let modeline = modeline::generate(dialog.width, dialog.height, dialog.refresh, dialog.timing);
+                egui::Grid::new("custom_mode_timings").show(ui, |ui| {
+                    ui.label("Pixel clock");
+                    ui.label(format!("{:.2} MHz", modeline.clock_khz as f64 / 1000.0));
+                    ui.end_row();
+                    ui.label("Horizontal");
+                    ui.label(format!(
+                        "{} {} {} {}  ({:.2} kHz)",
+                        modeline.hdisplay, modeline.hsync_start, modeline.hsync_end, modeline.htotal, modeline.hsync_khz()
+                    ));
+                    ui.end_row();
+                    ui.label("Vertical");
+                    ui.label(format!("{} {} {} {}", modeline.vdisplay, modeline.vsync_start, modeline.vsync_end, modeline.vtotal));
+                    ui.end_row();
+                    ui.label("Refresh");
+                    ui.label(format!("{:.2} Hz", modeline.refresh()));
+                    ui.end_row();
+                });
+                if modeline.hdisplay != dialog.width {
+                    ui.label(format!("Width gets rounded down to {}, CVT needs a multiple of 8", modeline.hdisplay));
+                }
+                ui.label(egui::RichText::new(format!("xrandr --newmode {}", modeline.newmode_args().join(" "))).monospace().small());
+                if ui.button("Add mode").on_hover_text("Selects it for this output. It gets created when you Apply").clicked() {
+                    added = Some(modeline);
+                }
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+
+        if let Some(modeline) = added {
+            // X keeps one mode per name. If there's one already it has to be this one or Apply would pick up the old timings
+            let existing = xrandr::get_verbose_outputs()
+                .map(|outputs| outputs.into_values().flat_map(|info| info.modes).find(|mode| mode.name == modeline.name));
+            match existing {
+                Err(e) => dialog.error = Some(e),
+                Ok(Some(existing)) if !existing.same_timings(&modeline) => {
+                    dialog.error = Some(format!(
+                        "There's already a mode called {} with different timings. Remove it with xrandr --rmmode {} or pick another refresh rate",
+                        modeline.name, modeline.name
+                    ));
+                }
+                _ => {
+                    let output = dialog.output.clone();
+                    if let Some(monitor) = self.monitors.iter_mut().find(|monitor| monitor.id == output) {
+                        monitor.proposed_resolution = Some((modeline.hdisplay, modeline.vdisplay));
+                        monitor.proposed_rate = Some(modeline.refresh() as f32);
+                        monitor.add_custom_mode(modeline);
+                    }
+                    open = false;
+                }
+            }
+        }
+        if !open {
+            self.custom_mode = None;
+        }
+    }
+
+    // Re-read xrandr without throwing away anything the user is in the middle of
+    fn refresh(&mut self) {
+        self.providers = providers::get_providers();
+        self.connectors = xrandr::get_connectors();
+        let (fresh, screen) = match get_monitors_from_xrandr() {
+            Ok(read) => read,
+            Err(e) => {
+                self.apply_error = Some(e); // Keep showing what we had
+                return;
+            }
+        };
+        let old = std::mem::take(&mut self.monitors);
+        self.screen = screen;
+        let (monitors, remap) = refresh::merge(old, fresh);
+        self.monitors = monitors;
+        // Indices move around when outputs come and go
+        self.selected_monitor = self.selected_monitor.and_then(|idx| remap.get(idx).copied().flatten());
+        self.align_target = self.align_target.and_then(|idx| remap.get(idx).copied().flatten());
+        // Outputs that came on outside brandr don't have the tint, calibration or ICC atom yet
+        self.night_light_shown = self.night_light.active_temperature();
+        if let Err(e) = apply::apply_color(&self.monitors, self.night_light_shown) {
+            self.apply_error = Some(e);
+        }
+        self.history.rebase(&self.monitors);
+    }
+
+    // Remember the profile for this monitor's EDID and put it on straight away
+    fn attach_icc(&mut self, index: usize, path: std::path::PathBuf) {
+        let monitor = &mut self.monitors[index];
+        let Some(edid) = monitor.edid.clone() else { return };
+        let profile = match icc::Profile::load(&path) {
+            Ok(profile) => profile,
+            Err(e) => {
+                self.apply_error = Some(e);
+                return;
+            }
+        };
+        let calibrated = profile.vcgt.is_some();
+        monitor.icc = Some(Ok(profile));
+        if calibrated {
+            // The curve takes over from software brightness and gamma
+            (monitor.brightness, monitor.gamma) = (1.0, [1.0; 3]);
+            (monitor.proposed_brightness, monitor.proposed_gamma) = (1.0, [1.0; 3]);
+            monitor.shown_color = (1.0, [1.0; 3]);
+        }
+        let saved = config::update_config(|config| {
+            config.icc_profiles.retain(|assignment| assignment.edid != edid);
+            config.icc_profiles.push(icc::Assignment { edid, path });
+        });
+        self.apply_error = saved.and_then(|_| apply::apply_color(&self.monitors, self.night_light_shown)).err();
+    }
+
+    fn detach_icc(&mut self, index: usize) {
+        let monitor = &mut self.monitors[index];
+        let was_calibrated = monitor.calibration().is_some();
+        monitor.icc = None;
+        let edid = monitor.edid.clone();
+        let saved = config::update_config(|config| config.icc_profiles.retain(|assignment| Some(&assignment.edid) != edid.as_ref()));
+        // apply_color leaves ramps alone when nothing needs them, so take the curve off this one by hand
+        let ramps = if was_calibrated { gamma::set_ramps(&self.monitors[index..=index], self.night_light_shown) } else { Ok(()) };
+        self.apply_error = saved.and(ramps).and_then(|_| apply::apply_color(&self.monitors, self.night_light_shown)).err();
+    }
+
+    fn start_identify(&mut self) {
+        // Real screen rectangles so each label lands on its own output
+        self.identify_windows = cap::get_output_geometry()
+            .into_iter()
+            .filter_map(|geometry| {
+                let monitor = self.monitors.iter().find(|m| m.id == geometry.name)?;
+                let label = monitor_label(monitor);
+                Some((geometry, label))
+            })
+            .collect();
+        self.identify_until = Some(Instant::now() + Duration::from_secs(3));
+    }
+
+    fn draw_identify_windows(&mut self, ctx: &egui::Context) {
+        let Some(until) = self.identify_until else { return };
+        let now = Instant::now();
+        if now >= until {
+            self.identify_until = None;
+            self.identify_windows.clear();
+            return;
+        }
+
+        // Borderless on top window in the middle of each output. Viewport positions are in points not pixels
+        let pixels_per_point = ctx.pixels_per_point();
+        let window_size = egui::vec2(600.0, 250.0);
+        for (geometry, label) in &self.identify_windows {
+            let center = egui::pos2(
+                (geometry.x as f32 + geometry.width as f32 / 2.0) / pixels_per_point,
+                (geometry.y as f32 + geometry.height as f32 / 2.0) / pixels_per_point,
+            );
+            ctx.show_viewport_immediate(
+                egui::ViewportId::from_hash_of(("identify", &geometry.name)),
+                egui::ViewportBuilder::default()
+                    .with_title(format!("Identify {}", geometry.name))
+                    .with_decorations(false)
+                    .with_always_on_top()
+                    .with_resizable(false)
+                    .with_position(center - window_size / 2.0)
+                    .with_inner_size(window_size),
+                |ctx, _class| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        ui.centered_and_justified(|ui| {
+                            ui.label(egui::RichText::new(label).size(48.0).strong());
+                        });
+                    });
+                },
+            );
+        }
+        ctx.request_repaint_after(until - now); // Make sure they go away even if nothing else is happening
+    }
+
     fn draw_monitor_settings(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
         if let Some(selected_idx) = self.selected_monitor {
+            let other_ids: Vec<(usize, String)> = self.monitors.iter()
+                .enumerate()
+                .filter(|(idx, monitor)| *idx != selected_idx && monitor.on_canvas())
+                .map(|(idx, monitor)| (idx, monitor.id.clone()))
+                .collect();
+            if self.align_target.map_or(true, |target| !other_ids.iter().any(|(idx, _)| *idx == target)) {
+                self.align_target = other_ids.first().map(|(idx, _)| *idx);
+            }
+            let mut align_target = self.align_target;
+            let mut align_request: Option<(placement::Axis, placement::Align)> = None;
+            let mut made_primary = false;
+            let mut open_custom_mode = false;
+            let mut read_ddc = false;
+            let mut ddc_error: Option<String> = None;
+            let mut attach_icc: Option<std::path::PathBuf> = None;
+            let mut detach_icc = false;
+            let mut icc_path = std::mem::take(&mut self.icc_path);
+            let vrr_driver = self.vrr_driver.clone();
+            let (mut split_count, mut split_rows) = (self.split_count, self.split_rows);
             let monitor = &mut self.monitors[selected_idx];
     
             // Create a separate box for monitor settings
@@ -396,74 +1092,431 @@ impl MonitorApp {
     
                 // Parse and update proposed resolution if valid
                 if let Some((width, height)) = parse_resolution_input(&resolution_input) {
+                    if monitor.proposed_resolution != Some((width, height)) {
+                        monitor.proposed_rate = None; // Old rate might not exist on the new mode, let xrandr pick
+                    }
                     monitor.proposed_resolution = Some((width, height));
                 }
+
+                // Rates the proposed mode offers. Auto lets xrandr pick
+                let rates: Vec<f32> = monitor.modes.iter()
+                    .find(|(size, _)| Some(*size) == monitor.proposed_resolution)
+                    .map(|(_, rates)| rates.clone())
+                    .unwrap_or_default();
+                ui.horizontal(|ui| {
+                    ui.label("Rate:");
+                    egui::ComboBox::from_id_source("rate_combobox")
+                        .selected_text(monitor.proposed_rate.map(|rate| format!("{:.2} Hz", rate)).unwrap_or_else(|| String::from("Auto")))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut monitor.proposed_rate, None, "Auto");
+                            for rate in rates {
+                                ui.selectable_value(&mut monitor.proposed_rate, Some(rate), format!("{:.2} Hz", rate));
+                            }
+                        });
+                    ui.label("Rotation:");
+                    egui::ComboBox::from_id_source("rotation_combobox")
+                        .selected_text(&monitor.proposed_orientation)
+                        .show_ui(ui, |ui| {
+                            for rotation in ROTATIONS {
+                                ui.selectable_value(&mut monitor.proposed_orientation, rotation.to_string(), rotation);
+                            }
+                        });
+                    made_primary = ui.checkbox(&mut monitor.proposed_primary, "Primary").changed() && monitor.proposed_primary;
+                    open_custom_mode = ui.button("Custom mode...").clicked();
+                });
+
+                // Software brightness and gamma. Needs the output running. Shows on screen right away, Apply keeps it
+                if monitor.enabled && monitor.calibration().is_some() {
+                    ui.label("Brightness and gamma come from the ICC profile's calibration");
+                } else if monitor.enabled {
+                    ui.horizontal(|ui| {
+                        ui.label("Brightness:");
+                        ui.add(egui::Slider::new(&mut monitor.proposed_brightness, 0.1..=1.5).fixed_decimals(2));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Gamma:");
+                        for (channel, label) in monitor.proposed_gamma.iter_mut().zip(["R", "G", "B"]) {
+                            ui.add(egui::Slider::new(channel, 0.3..=3.0).logarithmic(true).fixed_decimals(2).text(label));
+                        }
+                    });
+                }
+                if monitor.enabled {
+
+                    // The monitor's own brightness, contrast and input over DDC/CI. Goes straight to the monitor, not part of Apply
+                    egui::CollapsingHeader::new("Hardware (DDC/CI)").id_source("ddc_section").show(ui, |ui| {
+                        match &mut monitor.ddc {
+                            None => read_ddc = ui.button("Read from monitor").clicked(),
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::RED, e.as_str());
+                                read_ddc = ui.button("Try again").clicked();
+                            }
+                            Some(Ok(controls)) => ddc_error = draw_ddc_controls(ui, controls),
+                        }
+                    });
+                }
+
+                // Variable refresh. Can only be switched here if the driver has a property for it, otherwise say what decides it
+                ui.horizontal(|ui| {
+                    ui.label("Variable refresh:");
+                    if !vrr::capable(&monitor.properties) {
+                        ui.label("not supported by this monitor or connection");
+                    } else if let Some(index) = vrr::control(&monitor.proposed_properties) {
+                        let property = &mut monitor.proposed_properties[index];
+                        let mut on = vrr::is_on(property);
+                        if ui.checkbox(&mut on, "On").changed() {
+                            vrr::switch(property, on);
+                        }
+                    } else {
+                        match &vrr_driver {
+                            Some((driver, true)) => {
+                                ui.label(format!("supported, on for fullscreen apps ({} VariableRefresh is on)", driver));
+                            }
+                            Some((driver, false)) => {
+                                ui.label(format!("supported, but off in the {} driver", driver))
+                                    .on_hover_text("Add Option \"VariableRefresh\" \"true\" to the Device section in xorg.conf and restart X");
+                            }
+                            None => {
+                                ui.label("supported, up to the driver")
+                                    .on_hover_text("amdgpu and modesetting use Option \"VariableRefresh\" in xorg.conf, NVIDIA uses G-SYNC Compatible in nvidia-settings");
+                            }
+                        }
+                    }
+                });
+
+                // Remembered by EDID, so it needs one. Loads straight away and again on every Apply and hotplug
+                egui::CollapsingHeader::new("Color profile (ICC)").id_source("icc_section").show(ui, |ui| {
+                    if monitor.edid.is_none() {
+                        ui.label("This monitor doesn't give an EDID so a profile can't be remembered for it");
+                        return;
+                    }
+                    match &monitor.icc {
+                        None => {
+                            ui.label("None attached");
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(egui::Color32::RED, e.as_str());
+                        }
+                        Some(Ok(profile)) => {
+                            let calibration = if profile.vcgt.is_some() { "with calibration curve" } else { "no calibration curve" };
+                            ui.label(format!("{} ({})", profile.description, calibration))
+                                .on_hover_text(profile.path.display().to_string());
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut icc_path).hint_text("/path/to/profile.icc"));
+                        if ui.add_enabled(!icc_path.trim().is_empty(), egui::Button::new("Attach")).clicked() {
+                            attach_icc = Some(std::path::PathBuf::from(icc_path.trim()));
+                        }
+                        if monitor.icc.is_some() {
+                            detach_icc = ui.button("Detach").clicked();
+                        }
+                    });
+                });
+
+                // RandR output properties from the driver. Edits go out as --set on Apply
+                if !monitor.proposed_properties.is_empty() {
+                    egui::CollapsingHeader::new("Advanced").id_source("properties_section").show(ui, |ui| {
+                        egui::Grid::new("properties_grid").num_columns(2).striped(true).show(ui, |ui| {
+                            for property in &mut monitor.proposed_properties {
+                                ui.label(&property.name);
+                                draw_property(ui, property);
+                                ui.end_row();
+                            }
+                        });
+                    });
+                }
+
+                // Exact pixel position on the X screen. The canvas follows as you type
+                if monitor.on_canvas() {
+                    ui.horizontal(|ui| {
+                        ui.label("Position:");
+                        ui.add(egui::DragValue::new(&mut monitor.proposed_position.0).prefix("X: "));
+                        ui.add(egui::DragValue::new(&mut monitor.proposed_position.1).prefix("Y: "));
+                    });
+
+                    if let Some(target) = align_target {
+                        ui.horizontal(|ui| {
+                            ui.label("Align with");
+                            let target_id = other_ids.iter().find(|(idx, _)| *idx == target).map(|(_, id)| id.as_str()).unwrap_or("");
+                            egui::ComboBox::from_id_source("align_target_combobox")
+                                .selected_text(target_id)
+                                .show_ui(ui, |ui| {
+                                    for (idx, id) in &other_ids {
+                                        ui.selectable_value(&mut align_target, Some(*idx), id);
+                                    }
+                                });
+                            for (label, axis, align) in [
+                                ("Top", placement::Axis::Vertical, placement::Align::Start),
+                                ("Middle", placement::Axis::Vertical, placement::Align::Center),
+                                ("Bottom", placement::Axis::Vertical, placement::Align::End),
+                                ("Left", placement::Axis::Horizontal, placement::Align::Start),
+                                ("Center", placement::Axis::Horizontal, placement::Align::Center),
+                                ("Right", placement::Axis::Horizontal, placement::Align::End),
+                            ] {
+                                if ui.button(label).clicked() {
+                                    align_request = Some((axis, align));
+                                }
+                            }
+                        });
+                    }
+
+                    // Cut it into virtual monitors so the window manager tiles each piece on its own
+                    ui.horizontal(|ui| {
+                        ui.label("Split into");
+                        ui.add(egui::DragValue::new(&mut split_count).range(2..=8));
+                        ui.selectable_value(&mut split_rows, false, "Columns");
+                        ui.selectable_value(&mut split_rows, true, "Rows");
+                        if ui.button("Split").clicked() {
+                            let size_mm = rotate_size(&monitor.proposed_orientation, monitor.size_mm);
+                            monitor.proposed_virtual = virtual_monitor::split(&monitor.id, monitor.screen_size(), size_mm, split_count, split_rows);
+                        }
+                        if !monitor.proposed_virtual.is_empty() && ui.button("Remove split").clicked() {
+                            monitor.proposed_virtual.clear();
+                        }
+                    });
+                }
+
+                // Panning shows part of a bigger area and scrolls with the mouse. It can't be smaller than the mode
+                if monitor.proposed_status {
+                    let shown = monitor.screen_size();
+                    let panning = &mut monitor.proposed_panning;
+                    ui.horizontal(|ui| {
+                        let mut on = panning.enabled();
+                        if ui.checkbox(&mut on, "Panning").changed() {
+                            panning.size = if on { (shown.0 * 2, shown.1) } else { (0, 0) };
+                            panning.tracking = panning::Area::default();
+                        }
+                        if on {
+                            ui.add(egui::DragValue::new(&mut panning.size.0).range(shown.0..=32768).prefix("W: "));
+                            ui.add(egui::DragValue::new(&mut panning.size.1).range(shown.1..=32768).prefix("H: "));
+                        }
+                    });
+                    if panning.enabled() {
+                        ui.horizontal(|ui| {
+                            ui.label("Tracking area:")
+                                .on_hover_text("Where the mouse has to be for this output to follow it. All zero means the panning area");
+                            let tracking = &mut panning.tracking;
+                            ui.add(egui::DragValue::new(&mut tracking.width).range(0..=32768).prefix("W: "));
+                            ui.add(egui::DragValue::new(&mut tracking.height).range(0..=32768).prefix("H: "));
+                            ui.add(egui::DragValue::new(&mut tracking.x).prefix("X: "));
+                            ui.add(egui::DragValue::new(&mut tracking.y).prefix("Y: "));
+                        });
+                    }
+                }
             });
+            self.split_count = split_count;
+            self.split_rows = split_rows;
+            self.icc_path = icc_path;
+            if let Some(path) = attach_icc {
+                self.attach_icc(selected_idx, path);
+            }
+            if detach_icc {
+                self.detach_icc(selected_idx);
+            }
+            if read_ddc {
+                let monitor = &mut self.monitors[selected_idx];
+                monitor.ddc = Some(
+                    ddc::find_bus(&monitor.id, monitor.edid.as_ref())
+                        .ok_or_else(|| format!("Couldn't find the i2c bus for {}. Is the i2c-dev module loaded?", monitor.id))
+                        .and_then(ddc::Controls::read),
+                );
+            }
+            if ddc_error.is_some() {
+                self.apply_error = ddc_error;
+            }
+
+            self.align_target = align_target;
+            if open_custom_mode {
+                // Start from what it's set to so tweaking the refresh rate is one edit
+                let monitor = &self.monitors[selected_idx];
+                let (width, height) = monitor.proposed_resolution.unwrap_or(monitor.preferred_mode());
+                self.custom_mode = Some(CustomModeDialog {
+                    output: monitor.id.clone(),
+                    width,
+                    height,
+                    refresh: monitor.proposed_rate.map_or(60.0, |rate| rate.round() as f64),
+                    timing: modeline::Timing::Cvt,
+                    error: None,
+                });
+            }
+            if made_primary {
+                // Only one primary
+                for (idx, monitor) in self.monitors.iter_mut().enumerate() {
+                    monitor.proposed_primary = idx == selected_idx;
+                }
+            }
+            if let (Some((axis, align)), Some(target)) = (align_request, align_target) {
+                placement::align_to(&mut self.monitors, selected_idx, axis, align, target);
+            }
         }
     
-        // Check if any monitor settings have changed
-        let mut was_change = false;
-        for monitor in &mut self.monitors {
-            if monitor.position != monitor.initial_scaled_position || !monitor.enabled {
-                was_change = true;
-                break;
+        // Size of the whole X screen (--fb). Worked out from the layout unless picked by hand, which is only needed when
+        // panning or scaling leaves xrandr guessing wrong
+        let needed = apply::framebuffer_size(&self.monitors);
+        ui.horizontal(|ui| {
+            ui.label("Screen size:");
+            let mut custom = self.framebuffer.is_some();
+            if ui.checkbox(&mut custom, "Custom").changed() {
+                self.framebuffer = if custom { Some(needed) } else { None };
             }
-            if let Some((width, height)) = monitor.proposed_resolution {
-                if (width != monitor.resolution.0 || height != monitor.resolution.1) {
-                    was_change = true;
-                    break;
+            match &mut self.framebuffer {
+                Some((width, height)) => {
+                    ui.add(egui::DragValue::new(width).range(1..=32768).prefix("W: "));
+                    ui.add(egui::DragValue::new(height).range(1..=32768).prefix("H: "));
+                }
+                None => {
+                    ui.label(format!("{}x{}", needed.0, needed.1));
                 }
             }
-            if monitor.proposed_status != monitor.enabled{
-                was_change = true;
-                break;
+        });
+        // Same check Apply does, shown while dragging so it's clear which monitor to pull back
+        if let Err(e) = apply::check_screen_size(&self.monitors, self.framebuffer, self.screen.as_ref()) {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+
+        // Outputs brandr doesn't lay out. One that got unplugged while on still holds a CRTC and screen space
+        let stale: Vec<&xrandr::Connector> = self.connectors.iter().filter(|connector| connector.is_stale()).collect();
+        for connector in &stale {
+            if let Some(area) = connector.crtc {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("{} is disconnected but still on at {}", connector.id, area.arg()),
+                );
             }
         }
+        if !stale.is_empty() {
+            ui.checkbox(&mut self.turn_off_stale, "Turn them off on Apply");
+        }
+        egui::CollapsingHeader::new(format!("Connectors ({})", self.connectors.len())).id_source("connectors_section").show(ui, |ui| {
+            egui::Grid::new("connectors_grid").num_columns(3).striped(true).show(ui, |ui| {
+                for connector in &self.connectors {
+                    ui.label(&connector.id);
+                    ui.label(connector.connection.label());
+                    ui.label(connector.crtc.map_or(String::from("off"), |area| area.arg()));
+                    ui.end_row();
+                }
+            });
+        });
+
+        // Check if any monitor settings have changed
+        let was_change = self.monitors.iter().any(|monitor| monitor.has_changes())
+            || self.framebuffer.map_or(false, |size| Some(size) != self.screen.map(|screen| screen.current))
+            || (self.turn_off_stale && !stale.is_empty());
     
-        if was_change && ui.button("Apply").clicked() {
-            let mut command = String::from("xrandr");
-    
-            // Loop through monitors and add their settings to the command only if enabled
-            for monitor in &mut self.monitors {
-                if monitor.proposed_status == true {
-                    let position_x = if monitor.position != monitor.initial_scaled_position {
-                        (self.net_zero_x - monitor.position.x) as i32
-                    } else {
-                        monitor.position.x as i32
-                    };
-                    let position_y = if monitor.position != monitor.initial_scaled_position {
-                        (self.net_zero_y - monitor.position.y) as i32
-                    } else {
-                        monitor.position.y as i32
-                    };
-    
-                    if let Some((width, height)) = monitor.proposed_resolution {
-                        command.push_str(&format!(
-                            " --output {} --mode {}x{} --pos {}x{}",
-                            monitor.id, width, height, position_x.abs(), position_y
-                        ));
-                    }
-                    monitor.enabled = true; // Set mon to enabled so next time it loops it doesn't break
-                } else {
-                    // If monitor is disabled, add command to turn it off
-                    command.push_str(&format!(" --output {} --off", monitor.id));
-                    monitor.enabled = false;  // disable the monitor in its settings
+        ui.horizontal(|ui| {
+            if was_change && ui.button("Apply").clicked() {
+                // Execute the combined xrandr command for all monitors
+                self.apply_layout();
+            }
+            if ui.add_enabled(self.history.can_undo(), egui::Button::new("Undo")).on_hover_text("Ctrl+Z").clicked() {
+                self.history.undo(&mut self.monitors);
+            }
+            if ui.add_enabled(self.history.can_redo(), egui::Button::new("Redo")).on_hover_text("Ctrl+Shift+Z").clicked() {
+                self.history.redo(&mut self.monitors);
+            }
+            if ui.add_enabled(was_change, egui::Button::new("Reset to current")).clicked() {
+                for monitor in &mut self.monitors {
+                    monitor.reset_proposed();
                 }
             }
-    
-            // Execute the combined xrandr command for all monitors
-            let _output = std::process::Command::new("sh")
-                .arg("-c")
-                .arg(&command)
-                .output()
-                .expect("Failed to apply monitor settings");
-            //dbg!(command);
+        });
+        if let Some(error) = &self.apply_error {
+            ui.colored_label(egui::Color32::RED, error);
         }
     }
     
 }
 
 
+// Same label on the canvas and the identify windows so you can match them up
+fn monitor_label(monitor: &Monitor) -> String {
+    let (width, height) = if monitor.enabled { monitor.resolution } else { monitor.proposed_resolution.unwrap_or(monitor.preferred_mode()) };
+    match monitor.edid.as_ref().and_then(|edid| edid.name.as_ref()) {
+        Some(name) => format!("{} - {}\n{}x{}", monitor.id, name, width, height),
+        None => format!("{}\n{}x{}", monitor.id, width, height),
+    }
+}
+
+// Sliders for what the monitor answered over DDC/CI. Gives back why a write failed
+fn draw_ddc_controls(ui: &mut egui::Ui, controls: &mut ddc::Controls) -> Option<String> {
+    let mut error = None;
+    let bus = controls.bus.clone();
+    for (label, code, value) in [
+        ("Brightness:", ddc::VCP_BRIGHTNESS, &mut controls.brightness),
+        ("Contrast:", ddc::VCP_CONTRAST, &mut controls.contrast),
+    ] {
+        if let Some((current, max)) = value {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                let response = ui.add(egui::Slider::new(current, 0..=*max));
+                // Every write ties the monitor up for ~50ms so only send it once the slider is let go
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    error = ddc::set(&bus, code, *current).err();
+                }
+            });
+        }
+    }
+    if let Some(input) = &mut controls.input_source {
+        ui.horizontal(|ui| {
+            ui.label("Input:");
+            let before = *input;
+            egui::ComboBox::from_id_source("ddc_input_combobox")
+                .selected_text(ddc::input_source_label(*input))
+                .show_ui(ui, |ui| {
+                    for (source, label) in ddc::INPUT_SOURCES {
+                        ui.selectable_value(input, source, label);
+                    }
+                });
+            if *input != before {
+                error = ddc::set(&bus, ddc::VCP_INPUT_SOURCE, *input).err();
+            }
+        });
+    }
+    error
+}
+
+// Widget that fits the property. A 0 to 1 range is really an on/off switch
+fn draw_property(ui: &mut egui::Ui, property: &mut xrandr::OutputProperty) {
+    match &property.kind {
+        xrandr::PropertyKind::Choice(values) => {
+            egui::ComboBox::from_id_source(("property_combobox", property.name.as_str()))
+                .selected_text(property.value.as_str())
+                .show_ui(ui, |ui| {
+                    for value in values {
+                        ui.selectable_value(&mut property.value, value.clone(), value);
+                    }
+                });
+        }
+        xrandr::PropertyKind::Range(0, 1) => {
+            let mut on = property.value == "1";
+            if ui.checkbox(&mut on, "").changed() {
+                property.value = String::from(if on { "1" } else { "0" });
+            }
+        }
+        xrandr::PropertyKind::Range(min, max) => {
+            let mut number: i64 = property.value.parse().unwrap_or(*min);
+            if ui.add(egui::DragValue::new(&mut number).range(*min..=*max)).changed() {
+                property.value = number.to_string();
+            }
+        }
+        xrandr::PropertyKind::ReadOnly => {
+            ui.label(property.value.as_str()).on_hover_text("Read only");
+        }
+    }
+}
+
+// Which output this is in the bottom left corner. Dark box behind it so it shows up on any screenshot
+fn paint_label(ui: &egui::Ui, rect: egui::Rect, text: String) {
+    let galley = ui.painter().layout_no_wrap(
+        text,
+        egui::TextStyle::Small.resolve(ui.style()),
+        egui::Color32::WHITE,
+    );
+    let label_rect = egui::Align2::LEFT_BOTTOM.anchor_size(rect.left_bottom() + egui::vec2(4.0, -4.0), galley.size());
+    ui.painter().rect_filled(label_rect.expand(2.0), 2.0, egui::Color32::from_black_alpha(160));
+    ui.painter().galley(label_rect.min, galley, egui::Color32::WHITE);
+}
+
 fn parse_resolution_input(input: &str) -> Option<(u32, u32)> {
     let parts: Vec<&str> = input.split('x').collect();
     if parts.len() == 2 {
@@ -475,33 +1528,180 @@ fn parse_resolution_input(input: &str) -> Option<(u32, u32)> {
 }
 
 impl App for MonitorApp {
-    
+
+    // egui hands Tab to its own widget focus before update() runs, which would eat every shortcut after it.
+    // Take it out here when nobody is typing so it can cycle monitors instead
+    fn raw_input_hook(&mut self, ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        raw_input.events.retain(|event| match event {
+            egui::Event::Key { key: egui::Key::Tab, pressed, modifiers, .. } => {
+                if *pressed {
+                    self.pending_tabs += if modifiers.shift { -1 } else { 1 };
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Undo/redo shortcuts. Not while typing, text fields have their own Ctrl+Z
+        if !ctx.wants_keyboard_input() {
+            let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z);
+            let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+            if ctx.input_mut(|i| i.consume_shortcut(&redo)) { // Check shift first, plain Ctrl+Z matches with shift held too
+                self.history.redo(&mut self.monitors);
+            } else if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+                self.history.undo(&mut self.monitors);
+            }
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::F5)) {
+                self.refresh();
+            }
+            self.handle_layout_keys(ctx);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| { 
             self.draw_monitors(ui, ctx);   // Draw monitors on the pane. Can probably draw the rest here.
-            // Seperator added at the end of draw monitors
+            ui.separator();
             // Draw monitor settings in a different section of the UI
             self.draw_monitor_settings(ui, ctx);
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Refresh").on_hover_text("Re-read the outputs, keeping changes that haven't been applied (F5)").clicked() {
+                    self.refresh();
+                }
+                if ui.button("Identify").clicked() {
+                    self.start_identify();
+                }
+                if self.providers.len() > 1 {
+                    ui.toggle_value(&mut self.show_providers, "Providers").on_hover_text("GPUs and how their outputs are linked");
+                }
+                if ui.button("Fit to view").on_hover_text("Or double click the canvas").clicked() {
+                    self.fit_to_view();
+                }
+                ui.label("Preview quality:");
+                egui::ComboBox::from_id_source("preview_quality_combobox")
+                    .selected_text(self.preview_quality.label())
+                    .show_ui(ui, |ui| {
+                        for quality in cap::PreviewQuality::ALL {
+                            ui.selectable_value(&mut self.preview_quality, quality, quality.label());
+                        }
+                    });
+                ui.label("Privacy:");
+                egui::ComboBox::from_id_source("privacy_combobox")
+                    .selected_text(self.privacy.label())
+                    .show_ui(ui, |ui| {
+                        for privacy in cap::PrivacyMode::ALL {
+                            ui.selectable_value(&mut self.privacy, privacy, privacy.label());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                let settings = &mut self.night_light;
+                ui.checkbox(&mut settings.enabled, "Night light");
+                ui.add_enabled_ui(settings.enabled, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.temperature, night_light::MIN_TEMPERATURE..=night_light::NEUTRAL_TEMPERATURE)
+                            .step_by(100.0)
+                            .suffix(" K"),
+                    );
+                    ui.checkbox(&mut settings.scheduled, "Only from");
+                    ui.add_enabled(settings.scheduled, egui::TextEdit::singleline(&mut settings.start).desired_width(45.0));
+                    ui.label("to");
+                    ui.add_enabled(settings.scheduled, egui::TextEdit::singleline(&mut settings.end).desired_width(45.0));
+                    if settings.scheduled && !settings.schedule_valid() {
+                        ui.colored_label(egui::Color32::RED, "Times are HH:MM");
+                    }
+                });
+            });
         });
+        self.draw_identify_windows(ctx);
+
+        // Something got plugged in or changed outside brandr. Apply's own changes come back as events too, those are
+        // already on screen so only re-read if X changed since
+        if self.hotplug.as_ref().map_or(false, |changed| changed.swap(false, Ordering::Relaxed))
+            && (self.applied_times.is_none() || hotplug::config_times() != self.applied_times)
+        {
+            self.refresh();
+        }
+        self.update_night_light(ctx);
+        // Live preview for the brightness and gamma sliders
+        for monitor in self.monitors.iter_mut().filter(|monitor| monitor.enabled) {
+            if (monitor.proposed_brightness, monitor.proposed_gamma) != monitor.shown_color {
+                if let Err(e) = apply::preview_color(monitor, self.night_light_shown) {
+                    self.apply_error = Some(e);
+                }
+            }
+        }
+        self.draw_custom_mode_dialog(ctx);
+        self.draw_providers_window(ctx);
+
+        // Only settle an undo step once the mouse is up and nobody is typing so a whole drag or edit is one step
+        let editing = ctx.input(|i| i.pointer.any_down()) || ctx.wants_keyboard_input();
+        if !editing {
+            self.history.record(&self.monitors);
+            if self.night_light != self.night_light_saved {
+                let night_light = self.night_light.clone();
+                if let Err(e) = config::update_config(|config| config.night_light = night_light) {
+                    self.apply_error = Some(e);
+                }
+                self.night_light_saved = self.night_light.clone();
+            }
+        }
     }
 }
 
 
-fn get_monitors_from_xrandr() -> Vec<Monitor> {
+// Connected outputs, and the size limits of the X screen they're on
+fn get_monitors_from_xrandr() -> Result<(Vec<Monitor>, Option<xrandr::ScreenLimits>), String> {
     let output = Command::new("xrandr")
         .output()
-        .expect("Failed to execute xrandr");
+        .map_err(|e| format!("Failed to run xrandr: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("xrandr failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
 
-    let output_str = str::from_utf8(&output.stdout).expect("Failed to parse xrandr output");
-    let mut monitors = Vec::new();
+    let output_str = String::from_utf8_lossy(&output.stdout); // Odd bytes in a monitor name shouldn't cost the whole list
+    let mut monitors: Vec<Monitor> = Vec::new();
+    let verbose = xrandr::get_verbose_outputs()?; // EDID and friends only show up in --verbose
+    let listed_monitors = cap::get_output_geometry(); // Virtual monitors only show up in --listmonitors
+    let mut in_connected = false; // Mode lines belong to the last output line. Only keep them for connected ones
+    let mut screen = None;
+    // Set when brandr's night light is on the ramps. xrandr can't tell the tint apart from gamma, so read what's under it
+    let untinted = gamma::shown_temperature().map(gamma::untinted_colors).unwrap_or_default();
+    let icc_profiles = config::load_config().icc_profiles;
 
     for line in output_str.lines() {
-        if line.contains(" connected") {
+        if line.starts_with("Screen ") {
+            screen = xrandr::parse_screen_line(line);
+            in_connected = false;
+        } else if line.contains(" connected") {
+            in_connected = true;
             let parts: Vec<&str> = line.split_whitespace().collect();
             let id = parts[0];
+            let edid = verbose.get(id).and_then(|info| info.edid_identity());
+            let brightness = verbose.get(id).and_then(|info| info.brightness).unwrap_or(1.0);
+            let gamma = verbose.get(id).and_then(|info| info.gamma).unwrap_or([1.0; 3]);
+            let (brightness, gamma) = untinted.get(id).copied().unwrap_or((brightness, gamma));
+            let properties = verbose.get(id).map(|info| info.properties.clone()).unwrap_or_default();
+            let icc = edid.as_ref().and_then(|edid| icc::assigned_path(&icc_profiles, edid)).map(|path| icc::Profile::load(&path));
+            // A calibration curve owns the ramps. xrandr would read it back as some odd brightness and gamma
+            let calibrated = matches!(&icc, Some(Ok(profile)) if profile.vcgt.is_some());
+            let (brightness, gamma) = if calibrated { (1.0, [1.0; 3]) } else { (brightness, gamma) };
+            let primary = parts.contains(&"primary");
+            // "... (normal left inverted right x axis y axis) 598mm x 336mm" at the end
+            let millimeters: Vec<u32> = parts.iter().filter_map(|part| part.strip_suffix("mm")?.parse().ok()).collect();
+            let size_mm = match millimeters[..] {
+                [width, height] => (width, height),
+                _ => (0, 0),
+            };
 
             // Look for resolution and position in the line
-            if let Some(resolution_position) = parts.iter().find(|&s| s.contains('+')) {
+            if let Some(position_index) = parts.iter().position(|s| s.contains('+')) {
+                let resolution_position = parts[position_index];
                 // Extract resolution and position
                 let resolution_parts: Vec<&str> = resolution_position.split('x').collect();
                 let width = resolution_parts[0].parse::<u32>().unwrap_or(1920);
@@ -510,49 +1710,131 @@ fn get_monitors_from_xrandr() -> Vec<Monitor> {
                 let pos_x = height_position[1].parse::<f32>().unwrap_or(0.0);
                 let pos_y = height_position[2].parse::<f32>().unwrap_or(0.0);
 
+                // Rotation only gets printed right after the position when it isn't normal. "1080x1920+2560+0 left (normal left ..."
+                let orientation = match parts.get(position_index + 1) {
+                    Some(&rotation) if ROTATIONS.contains(&rotation) => rotation.to_string(),
+                    _ => String::from("normal"),
+                };
+                // xrandr prints the rotated size but --mode wants the unrotated one
+                let mode = if orientation == "left" || orientation == "right" { (height, width) } else { (width, height) };
+                let virtual_monitors = virtual_monitor::from_listmonitors(&listed_monitors, id, (pos_x as i32, pos_y as i32));
+                // xrandr reports the tracking area as the panning area when none was given
+                let panning = match verbose.get(id).and_then(|info| info.panning).filter(|area| !area.is_empty()) {
+                    Some(area) => panning::Panning {
+                        size: (area.width, area.height),
+                        tracking: verbose.get(id).and_then(|info| info.tracking).filter(|tracking| *tracking != area).unwrap_or_default(),
+                    },
+                    None => panning::Panning::default(),
+                };
+
                 monitors.push(Monitor {
                     id: id.to_string(),
+                    edid,
+                    icc,
+                    proposed_properties: properties.clone(),
+                    properties,
                     enabled: true,
-                    orientation: String::from("Landscape"),
-                    resolution: (width, height),
-                    proposed_resolution: Some((width, height)),
+                    primary,
+                    proposed_primary: primary,
+                    proposed_orientation: orientation.clone(),
+                    orientation,
+                    resolution: mode,
+                    proposed_resolution: Some(mode),
                     position: egui::Pos2::new(pos_x, pos_y),
-                    initial_scaled_position: egui::Pos2::new(0.0, 0.0),
-                    scale: 1.0,
+                    current_position: (pos_x as i32, pos_y as i32),
+                    proposed_position: (pos_x as i32, pos_y as i32),
                     proposed_status: true,
-                    screenshot: None,
-                    duplicate_of: None,
-                    being_dragged: false,
-                    last_screenshot_time: Instant::now(),
-                    texture: None,
-                    scale_x_factor: 0.0,
-                    scale_y_factor: 0.0,
+                    size_mm,
+                    proposed_virtual: virtual_monitors.clone(),
+                    virtual_monitors,
+                    panning,
+                    proposed_panning: panning,
+                    brightness,
+                    proposed_brightness: brightness,
+                    gamma,
+                    proposed_gamma: gamma,
+                    shown_color: (brightness, gamma),
+                    ..Default::default()
                 });
             } else {
                 // Monitor is connected but lacks resolution and position, mark as blank
                 monitors.push(Monitor {
                     id: id.to_string(),
+                    edid,
+                    icc,
+                    proposed_properties: properties.clone(),
+                    properties,
                     enabled: false,
-                    orientation: String::from("Landscape"),
+                    primary,
+                    proposed_primary: primary,
                     resolution: (1920, 1080), // Placeholder for blank monitors
                     proposed_resolution: None,
-                    position: egui::Pos2::new(0.0, 0.0),
-                    initial_scaled_position: egui::Pos2::new(0.0, 0.0),
-                    scale: 1.0,
                     proposed_status: false,
-                    screenshot: None,
-                    duplicate_of: None,
-                    being_dragged: false,
-                    last_screenshot_time: Instant::now(),
-                    texture: None,
-                    scale_x_factor: 0.0,
-                    scale_y_factor: 0.0,
+                    size_mm,
+                    ..Default::default()
                 });
             }
+        } else if line.starts_with(char::is_whitespace) && in_connected {
+            // "   2560x1440    143.97*+  59.95  " the * is the rate it's running at
+            if let (Some(monitor), Some((name, mode, rates, current, preferred))) = (monitors.last_mut(), parse_mode_line(line)) {
+                if current.is_some() {
+                    monitor.rate = current;
+                    monitor.proposed_rate = current;
+                }
+                if preferred {
+                    monitor.preferred = Some(mode);
+                }
+                // Custom modes like 2560x1080_75.00 are their own line. Keep the name and list the rate under the plain size
+                if name != format!("{}x{}", mode.0, mode.1) {
+                    for rate in &rates {
+                        monitor.mode_names.push((mode, *rate, name.clone()));
+                    }
+                }
+                match monitor.modes.iter_mut().find(|(size, _)| *size == mode) {
+                    Some((_, known_rates)) => known_rates.extend(rates),
+                    None => monitor.modes.push((mode, rates)),
+                }
+            }
+        } else {
+            in_connected = false;
         }
     }
 
-    monitors
+    Ok((monitors, screen))
+}
+
+const ROTATIONS: [&str; 4] = ["normal", "left", "inverted", "right"];
+
+// Sideways monitors swap width and height
+fn rotate_size(orientation: &str, (width, height): (u32, u32)) -> (u32, u32) {
+    if orientation == "left" || orientation == "right" {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+// Mode line from plain xrandr. Gives back the name, size, every rate, the current rate if this is the active mode and if it's the preferred mode
+fn parse_mode_line(line: &str) -> Option<(String, (u32, u32), Vec<f32>, Option<f32>, bool)> {
+    let mut parts = line.split_whitespace();
+    let name = parts.next()?;
+    let mode = modeline::size_from_name(name)?;
+    let mut rates = Vec::new();
+    let mut current = None;
+    let mut preferred = false;
+    for part in parts {
+        // Flags are stuck on the end of the rate. * is current, + is preferred. A lone + can come after a space too
+        if part.contains('+') {
+            preferred = true;
+        }
+        if let Ok(rate) = part.trim_end_matches(|c| c == '*' || c == '+').parse::<f32>() {
+            if part.contains('*') {
+                current = Some(rate);
+            }
+            rates.push(rate);
+        }
+    }
+    Some((name.to_string(), mode, rates, current, preferred))
 }
 
 
@@ -720,7 +2002,17 @@ impl eframe::App for MonitorApp {
 fn main() -> eframe::Result<()> {
     // So you can run multiple things from main. think the eframe is a loop
 
-    let monitors = get_monitors_from_xrandr();
+    // Any arguments means the command line, otherwise the gui
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
+    // Still open the window if xrandr can't be read, the error shows up under Apply
+    let (monitors, screen, xrandr_error) = match get_monitors_from_xrandr() {
+        Ok((monitors, screen)) => (monitors, screen, None),
+        Err(e) => (Vec::new(), None, Some(e)),
+    };
     // Debug print each monitor
     if monitors.is_empty() { // WOuld need something here
         println!("No monitors found.");
@@ -730,8 +2022,19 @@ fn main() -> eframe::Result<()> {
             //dbg!(mon.enabled);
         }
     }
-    let monitor_app = MonitorApp {
+    let config = config::load_config();
+    let mut monitor_app = MonitorApp {
         monitors,
+        preview_quality: config.preview_quality,
+        privacy: config.privacy,
+        night_light_saved: config.night_light.clone(),
+        night_light: config.night_light,
+        night_light_shown: gamma::shown_temperature(),
+        vrr_driver: vrr::driver_state(),
+        providers: providers::get_providers(),
+        screen,
+        connectors: xrandr::get_connectors(),
+        apply_error: xrandr_error,
         ..Default::default()
     };
     // Check if no monitors were found
@@ -741,20 +2044,55 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Monitor Manager",
         options,
-        Box::new(|_cc| {
+        Box::new(|cc| {
+             monitor_app.hotplug = hotplug::watch(cc.egui_ctx.clone());
              Ok(Box::new(monitor_app))
         }),
     )
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modeline::Timing;
+
+    #[test]
+    fn mode_line_flags() {
+        let (name, mode, rates, current, preferred) = parse_mode_line("   1920x1080     60.00*+  50.00    59.94  ").unwrap();
+        assert_eq!(name, "1920x1080");
+        assert_eq!(mode, (1920, 1080));
+        assert_eq!(rates, vec![60.0, 50.0, 59.94]);
+        assert_eq!(current, Some(60.0));
+        assert!(preferred);
+
+        // Preferred but not current, with the + after a space
+        let (_, _, rates, current, preferred) = parse_mode_line("   2560x1440     59.95 +  74.97  ").unwrap();
+        assert_eq!(rates, vec![59.95, 74.97]);
+        assert_eq!(current, None);
+        assert!(preferred);
+    }
+
+    #[test]
+    fn generated_modes_round_trip() {
+        // What xrandr lists after we added and picked our own modes
+        for timing in Timing::ALL {
+            let mode = modeline::generate(2560, 1080, 75.0, timing);
+            let line = format!("   {}  75.00*", mode.name);
+            let (name, size, _, current, _) = parse_mode_line(&line).unwrap();
+            assert_eq!(name, mode.name);
+            assert_eq!(size, (2560, 1080));
+            assert_eq!(current, Some(75.0));
+        }
+    }
+}
+
 /*
 To do:
 More display preferences right now its the bare minumum
 Ability to save display setups like arandr
 Snaping to each monitor like arandr
 Position feedback to the user to know if unaligned
-Resizing of the whole gui
 Detection when monitors change to run itself
 
 
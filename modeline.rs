@@ -0,0 +1,211 @@
+// Modelines for modes the monitor doesn't advertise. Same math as the cvt tool from xorg (VESA CVT 1.1)
+// so the results match what people paste from `cvt` into xrandr --newmode
+
+const H_GRANULARITY: u32 = 8; // Horizontal timings are whole character cells
+const MIN_V_PORCH: u32 = 3; // Lines
+const MIN_V_BPORCH: u32 = 6; // Lines
+const CLOCK_STEP: u32 = 250; // kHz
+
+// Normal blanking
+const MIN_VSYNC_BP: f64 = 550.0; // Minimum vsync + back porch in us
+const HSYNC_PERCENTAGE: u32 = 8; // Of the line period
+const M_PRIME: f64 = 600.0 * 128.0 / 256.0; // Blanking formula gradient scaled by K
+const C_PRIME: f64 = (40.0 - 20.0) * 128.0 / 256.0 + 20.0; // Blanking formula offset scaled by K and J
+
+// Reduced blanking. Meant for LCDs at 60Hz but plenty of panels take it at other rates
+const RB_MIN_VBLANK: f64 = 460.0; // us
+const RB_H_SYNC: u32 = 32; // Pixels
+const RB_H_BLANK: u32 = 160; // Pixels
+const RB_V_FPORCH: u32 = 3; // Lines
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+    Cvt,
+    CvtReducedBlanking,
+}
+
+impl Timing {
+    pub const ALL: [Timing; 2] = [Timing::Cvt, Timing::CvtReducedBlanking];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Timing::Cvt => "CVT",
+            Timing::CvtReducedBlanking => "CVT reduced blanking",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Modeline {
+    pub name: String,
+    pub clock_khz: u32,
+    pub hdisplay: u32,
+    pub hsync_start: u32,
+    pub hsync_end: u32,
+    pub htotal: u32,
+    pub vdisplay: u32,
+    pub vsync_start: u32,
+    pub vsync_end: u32,
+    pub vtotal: u32,
+    pub hsync_positive: bool,
+    pub vsync_positive: bool,
+}
+
+impl Modeline {
+    // What the monitor actually ends up running at after the clock got rounded
+    pub fn refresh(&self) -> f64 {
+        self.clock_khz as f64 * 1000.0 / (self.htotal as f64 * self.vtotal as f64)
+    }
+
+    pub fn hsync_khz(&self) -> f64 {
+        self.clock_khz as f64 / self.htotal as f64
+    }
+
+    // Everything but the name. X won't make a second mode with the same name, so one that's already there has to match
+    pub fn same_timings(&self, other: &Modeline) -> bool {
+        Modeline { name: other.name.clone(), ..self.clone() } == *other
+    }
+
+    // Everything after --newmode. "name clock hdisp hsyncstart hsyncend htotal vdisp vsyncstart vsyncend vtotal flags"
+    pub fn newmode_args(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            format!("{:.2}", self.clock_khz as f64 / 1000.0),
+            self.hdisplay.to_string(),
+            self.hsync_start.to_string(),
+            self.hsync_end.to_string(),
+            self.htotal.to_string(),
+            self.vdisplay.to_string(),
+            self.vsync_start.to_string(),
+            self.vsync_end.to_string(),
+            self.vtotal.to_string(),
+            String::from(if self.hsync_positive { "+hsync" } else { "-hsync" }),
+            String::from(if self.vsync_positive { "+vsync" } else { "-vsync" }),
+        ]
+    }
+}
+
+// Size from a mode name. Ours and cvt's have _rate on the end and an R after the size for reduced blanking,
+// interlaced ones from the EDID have an i
+pub fn size_from_name(name: &str) -> Option<(u32, u32)> {
+    let size = name.split('_').next()?.trim_end_matches(|c: char| !c.is_ascii_digit());
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+// Vsync width is how CVT tells the aspect ratio to the monitor
+fn vsync_lines(width: u32, height: u32) -> u32 {
+    if height % 3 == 0 && height * 4 / 3 == width {
+        4
+    } else if height % 9 == 0 && height * 16 / 9 == width {
+        5
+    } else if height % 10 == 0 && height * 16 / 10 == width {
+        6
+    } else if (height % 4 == 0 && height * 5 / 4 == width) || (height % 9 == 0 && height * 15 / 9 == width) {
+        7
+    } else {
+        10 // Anything else
+    }
+}
+
+pub fn generate(width: u32, height: u32, refresh: f64, timing: Timing) -> Modeline {
+    let hdisplay = width - width % H_GRANULARITY;
+    let vdisplay = height;
+    let vsync = vsync_lines(hdisplay, height);
+
+    let (hperiod, htotal, hsync_start, hsync_end, vtotal) = match timing {
+        Timing::Cvt => {
+            // Estimated line period in us
+            let hperiod = (1_000_000.0 / refresh - MIN_VSYNC_BP) / (vdisplay + MIN_V_PORCH) as f64;
+            let vsync_and_back_porch = ((MIN_VSYNC_BP / hperiod) as u32 + 1).max(vsync + MIN_V_PORCH);
+            let vtotal = vdisplay + vsync_and_back_porch + MIN_V_PORCH;
+
+            // Ideal blanking duty cycle, never under 20%
+            let blank_percentage = (C_PRIME - M_PRIME * hperiod / 1000.0).max(20.0);
+            let mut hblank = (hdisplay as f64 * blank_percentage / (100.0 - blank_percentage)) as u32;
+            hblank -= hblank % (2 * H_GRANULARITY);
+            let htotal = hdisplay + hblank;
+
+            let hsync_end = hdisplay + hblank / 2;
+            let mut hsync_start = hsync_end - htotal * HSYNC_PERCENTAGE / 100;
+            hsync_start += H_GRANULARITY - hsync_start % H_GRANULARITY;
+            (hperiod, htotal, hsync_start, hsync_end, vtotal)
+        }
+        Timing::CvtReducedBlanking => {
+            let hperiod = (1_000_000.0 / refresh - RB_MIN_VBLANK) / vdisplay as f64;
+            let vblank_lines = ((RB_MIN_VBLANK / hperiod) as u32 + 1).max(RB_V_FPORCH + vsync + MIN_V_BPORCH);
+            let vtotal = vdisplay + vblank_lines;
+
+            let htotal = hdisplay + RB_H_BLANK;
+            let hsync_end = hdisplay + RB_H_BLANK / 2;
+            let hsync_start = hsync_end - RB_H_SYNC;
+            (hperiod, htotal, hsync_start, hsync_end, vtotal)
+        }
+    };
+    let vsync_start = vdisplay + match timing {
+        Timing::Cvt => MIN_V_PORCH,
+        Timing::CvtReducedBlanking => RB_V_FPORCH,
+    };
+
+    // Pixel clock rounded down to the 0.25MHz step
+    let mut clock_khz = (htotal as f64 * 1000.0 / hperiod) as u32;
+    clock_khz -= clock_khz % CLOCK_STEP;
+
+    Modeline {
+        // Same naming as cvt so it never clashes with the EDID's plain WxH. R after the size for reduced blanking like cvt -r,
+        // otherwise both timings of one size and rate would get the same name
+        name: match timing {
+            Timing::Cvt => format!("{}x{}_{:.2}", hdisplay, height, refresh),
+            Timing::CvtReducedBlanking => format!("{}x{}R_{:.2}", hdisplay, height, refresh),
+        },
+        clock_khz,
+        hdisplay,
+        hsync_start,
+        hsync_end,
+        htotal,
+        vdisplay,
+        vsync_start,
+        vsync_end: vsync_start + vsync,
+        vtotal,
+        hsync_positive: timing == Timing::CvtReducedBlanking,
+        vsync_positive: timing == Timing::Cvt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn newmode_line(mode: &Modeline) -> String {
+        mode.newmode_args().join(" ")
+    }
+
+    #[test]
+    fn matches_cvt() {
+        // cvt 1920 1080 60
+        let mode = generate(1920, 1080, 60.0, Timing::Cvt);
+        assert_eq!(newmode_line(&mode), "1920x1080_60.00 173.00 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync");
+    }
+
+    #[test]
+    fn matches_cvt_reduced_blanking() {
+        // cvt -r 1920 1080 60, but with the rate in the name like ours
+        let mode = generate(1920, 1080, 60.0, Timing::CvtReducedBlanking);
+        assert_eq!(newmode_line(&mode), "1920x1080R_60.00 138.50 1920 1968 2000 2080 1080 1083 1088 1111 +hsync -vsync");
+    }
+
+    #[test]
+    fn rounded_width_everywhere() {
+        // 1366 isn't a whole number of cells. The name and the aspect ratio vsync go off the 1360 that gets used
+        let mode = generate(1366, 768, 60.0, Timing::Cvt);
+        assert_eq!(mode.name, "1360x768_60.00");
+        assert_eq!(mode.hdisplay, 1360);
+        assert_eq!(mode.vsync_end - mode.vsync_start, vsync_lines(1360, 768));
+        assert_eq!(size_from_name(&mode.name), Some((1360, 768)));
+
+        // 1280x1024 rounded is still 5:4, 1284 wide would have lost the 7 line vsync
+        let mode = generate(1284, 1024, 60.0, Timing::Cvt);
+        assert_eq!(mode.name, "1280x1024_60.00");
+        assert_eq!(mode.vsync_end - mode.vsync_start, 7);
+    }
+}
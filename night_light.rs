@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+// Warmer colors in the evening. The tint goes into the CRTC gamma ramps (gamma.rs) on top of the software brightness,
+// gamma and any calibration so nothing fights xrandr over them
+
+pub const NEUTRAL_TEMPERATURE: u32 = 6500; // Kelvin that leaves the colors alone
+pub const MIN_TEMPERATURE: u32 = 1000;
+
+// Lives in config.json next to the other defaults
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NightLight {
+    pub enabled: bool,
+    pub temperature: u32, // Kelvin while it's on
+    pub scheduled: bool, // Only between start and end instead of all the time
+    pub start: String, // "HH:MM" local time
+    pub end: String, // Can be past midnight
+}
+
+impl Default for NightLight {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            temperature: 4500,
+            scheduled: false,
+            start: String::from("20:00"),
+            end: String::from("07:00"),
+        }
+    }
+}
+
+impl NightLight {
+    // Kelvin the screens should be at right now. None when it's off or outside the schedule
+    pub fn active_temperature(&self) -> Option<u32> {
+        if !self.enabled {
+            return None;
+        }
+        if self.scheduled {
+            let (start, end) = (parse_time(&self.start)?, parse_time(&self.end)?);
+            let now = local_minutes();
+            let inside = if start <= end { now >= start && now < end } else { now >= start || now < end };
+            if !inside {
+                return None;
+            }
+        }
+        Some(self.temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE))
+    }
+
+    pub fn schedule_valid(&self) -> bool {
+        parse_time(&self.start).is_some() && parse_time(&self.end).is_some()
+    }
+}
+
+// "HH:MM" to minutes since midnight
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+fn local_minutes() -> u32 {
+    // Safe, both pointers are to locals. localtime_r picks up TZ the first time it's called
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut local);
+    }
+    (local.tm_hour * 60 + local.tm_min) as u32
+}
+
+// Red, green and blue multipliers for white at this temperature. Tanner Helland's fit of the blackbody colors,
+// scaled so 6500K comes out as plain white
+pub fn whitepoint(kelvin: u32) -> [f64; 3] {
+    let blackbody = |kelvin: f64| {
+        let t = kelvin / 100.0;
+        let red = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+        let green = if t <= 66.0 { 99.4708025861 * t.ln() - 161.1195681661 } else { 288.1221695283 * (t - 60.0).powf(-0.0755148492) };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        [red.clamp(0.0, 255.0), green.clamp(0.0, 255.0), blue.clamp(0.0, 255.0)]
+    };
+    let white = blackbody(NEUTRAL_TEMPERATURE as f64);
+    let color = blackbody(kelvin.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE) as f64);
+    [0, 1, 2].map(|channel| (color[channel] / white[channel]).min(1.0))
+}
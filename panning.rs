@@ -0,0 +1,54 @@
+// Panning (--panning). The output shows part of a bigger area and scrolls around it following the mouse.
+// Handy for projectors that can't do the resolution people expect
+
+// Rectangle on the X screen the way xrandr writes it, WxH+X+Y
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct Area {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Area {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // "1920x1080+0+0". Negative offsets show up as +-10 so take either sign after the +
+    pub fn parse(text: &str) -> Option<Area> {
+        let (size, offset) = text.trim().split_once('+')?;
+        let (width, height) = size.split_once('x')?;
+        let (x, y) = offset.split_once('+')?;
+        Some(Area { width: width.parse().ok()?, height: height.parse().ok()?, x: x.parse().ok()?, y: y.parse().ok()? })
+    }
+
+    pub fn arg(&self) -> String {
+        format!("{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct Panning {
+    pub size: (u32, u32), // Area it pans over. Starts at the output's position. 0x0 is no panning
+    pub tracking: Area, // Where the mouse makes it pan. Empty means the panning area
+}
+
+impl Panning {
+    pub fn enabled(&self) -> bool {
+        self.size.0 > 0 && self.size.1 > 0
+    }
+
+    // What goes after --panning for an output at `position`. 0x0 turns it off
+    pub fn arg(&self, position: (i32, i32)) -> String {
+        if !self.enabled() {
+            return String::from("0x0");
+        }
+        let area = Area { width: self.size.0, height: self.size.1, x: position.0, y: position.1 };
+        if self.tracking.is_empty() {
+            area.arg()
+        } else {
+            format!("{}/{}", area.arg(), self.tracking.arg())
+        }
+    }
+}
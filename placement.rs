@@ -0,0 +1,239 @@
+use crate::Monitor;
+
+// Put a monitor next to another one instead of doing --pos math by hand
+#[derive(Clone, Copy, PartialEq)]
+pub enum Relation {
+    LeftOf,
+    RightOf,
+    Above,
+    Below,
+    SameAs,
+}
+
+// Which edges line up. Start is top for left-of/right-of and left for above/below
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+impl Relation {
+    pub const ALL: [Relation; 5] = [Relation::LeftOf, Relation::RightOf, Relation::Above, Relation::Below, Relation::SameAs];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Relation::LeftOf => "Left of",
+            Relation::RightOf => "Right of",
+            Relation::Above => "Above",
+            Relation::Below => "Below",
+            Relation::SameAs => "Same as",
+        }
+    }
+
+    // Same names xrandr uses, with or without the dashes
+    pub fn parse(name: &str) -> Option<Relation> {
+        match name.trim_start_matches('-') {
+            "left-of" => Some(Relation::LeftOf),
+            "right-of" => Some(Relation::RightOf),
+            "above" => Some(Relation::Above),
+            "below" => Some(Relation::Below),
+            "same-as" => Some(Relation::SameAs),
+            _ => None,
+        }
+    }
+}
+
+impl Align {
+    pub const ALL: [Align; 3] = [Align::Start, Align::Center, Align::End];
+
+    pub fn label(&self, relation: Relation) -> &'static str {
+        let side_by_side = relation == Relation::LeftOf || relation == Relation::RightOf;
+        match (self, side_by_side) {
+            (Align::Start, true) => "Top aligned",
+            (Align::End, true) => "Bottom aligned",
+            (Align::Start, false) => "Left aligned",
+            (Align::End, false) => "Right aligned",
+            (Align::Center, _) => "Centered",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Align> {
+        match name {
+            "top" | "left" => Some(Align::Start),
+            "center" | "centered" => Some(Align::Center),
+            "bottom" | "right" => Some(Align::End),
+            _ => None,
+        }
+    }
+
+    // Only the words that make sense for the relation. Top and bottom are for side by side, left and right for stacked.
+    // Same as lines up both so it takes any of them
+    pub fn parse_for(name: &str, relation: Relation) -> Result<Align, String> {
+        let expected = match relation {
+            Relation::LeftOf | Relation::RightOf => ["top", "bottom", "center"],
+            Relation::Above | Relation::Below => ["left", "right", "center"],
+            Relation::SameAs => return Align::parse(name).ok_or_else(|| format!("Bad alignment {}, expected top, bottom, left, right or center", name)),
+        };
+        match Align::parse(name) {
+            Some(align) if expected.contains(&name) || name == "centered" => Ok(align),
+            _ => Err(format!("Bad alignment {} for {}, expected {}, {} or {}", name, relation.label().to_lowercase(), expected[0], expected[1], expected[2])),
+        }
+    }
+}
+
+// Where a monitor of `size` ends up on the X screen when placed against the anchor monitor
+pub fn resolve(relation: Relation, align: Align, size: (u32, u32), anchor_position: (i32, i32), anchor_size: (u32, u32)) -> (i32, i32) {
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    let (anchor_x, anchor_y) = anchor_position;
+    let (anchor_width, anchor_height) = (anchor_size.0 as i32, anchor_size.1 as i32);
+
+    let aligned = |start: i32, length: i32, anchor_length: i32| align_start(align, start, length, anchor_length);
+
+    match relation {
+        Relation::LeftOf => (anchor_x - width, aligned(anchor_y, height, anchor_height)),
+        Relation::RightOf => (anchor_x + anchor_width, aligned(anchor_y, height, anchor_height)),
+        Relation::Above => (aligned(anchor_x, width, anchor_width), anchor_y - height),
+        Relation::Below => (aligned(anchor_x, width, anchor_width), anchor_y + anchor_height),
+        Relation::SameAs => (aligned(anchor_x, width, anchor_width), aligned(anchor_y, height, anchor_height)),
+    }
+}
+
+// Where something `length` long starts so it lines up with the anchor's span. Center rounds toward the start
+fn align_start(align: Align, anchor_start: i32, length: i32, anchor_length: i32) -> i32 {
+    match align {
+        Align::Start => anchor_start,
+        Align::Center => anchor_start + (anchor_length - length) / 2,
+        Align::End => anchor_start + anchor_length - length,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Axis {
+    Horizontal, // Line up left, center or right edges
+    Vertical,   // Line up top, middle or bottom edges
+}
+
+// Line up one edge (or the middle) with another monitor without moving along the other axis
+pub fn align_to(monitors: &mut [Monitor], index: usize, axis: Axis, align: Align, anchor: usize) {
+    let (width, height) = monitors[index].screen_size();
+    let (anchor_width, anchor_height) = monitors[anchor].screen_size();
+    let (anchor_x, anchor_y) = monitors[anchor].proposed_position;
+    match axis {
+        Axis::Horizontal => monitors[index].proposed_position.0 = align_start(align, anchor_x, width as i32, anchor_width as i32),
+        Axis::Vertical => monitors[index].proposed_position.1 = align_start(align, anchor_y, height as i32, anchor_height as i32),
+    }
+}
+
+// Move monitors[index] against monitors[anchor] using the sizes they'll have once applied
+pub fn place(monitors: &mut [Monitor], index: usize, relation: Relation, align: Align, anchor: usize) -> Result<(), String> {
+    if index == anchor {
+        return Err(format!("{} can't be placed relative to itself", monitors[index].id));
+    }
+    let position = resolve(
+        relation,
+        align,
+        monitors[index].screen_size(),
+        monitors[anchor].proposed_position,
+        monitors[anchor].screen_size(),
+    );
+    monitors[index].proposed_position = position;
+    Ok(())
+}
+
+// How far monitors[index] has to move along the axis so one of its edges meets the next edge of another enabled monitor.
+// None when there is nothing further that way
+pub fn next_edge(monitors: &[Monitor], index: usize, axis: Axis, forward: bool) -> Option<i32> {
+    let edges = |monitor: &Monitor| {
+        let (width, height) = monitor.screen_size();
+        let (x, y) = monitor.proposed_position;
+        match axis {
+            Axis::Horizontal => [x, x + width as i32],
+            Axis::Vertical => [y, y + height as i32],
+        }
+    };
+    let own = edges(&monitors[index]);
+    monitors.iter()
+        .enumerate()
+        .filter(|(other, monitor)| *other != index && monitor.proposed_status)
+        .flat_map(|(_, monitor)| edges(monitor))
+        .flat_map(|edge| own.map(|own_edge| edge - own_edge))
+        .filter(|distance| if forward { *distance > 0 } else { *distance < 0 })
+        .min_by_key(|distance| distance.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_words_follow_the_axis() {
+        assert!(Align::parse_for("bottom", Relation::RightOf) == Ok(Align::End));
+        assert!(Align::parse_for("right", Relation::Below) == Ok(Align::End));
+        assert!(Align::parse_for("center", Relation::Above) == Ok(Align::Center));
+        assert!(Align::parse_for("right", Relation::SameAs) == Ok(Align::End));
+        // Would quietly have meant top
+        assert!(Align::parse_for("left", Relation::RightOf).is_err());
+        assert!(Align::parse_for("bottom", Relation::Above).is_err());
+        assert!(Align::parse_for("middle", Relation::SameAs).is_err());
+    }
+
+    #[test]
+    fn resolve_every_relation_and_alignment() {
+        // 1920x1080 against a 2560x1440 anchor at 100,200. The leftover is 640 across and 360 down
+        let expected = [
+            (Relation::LeftOf, [(-1820, 200), (-1820, 380), (-1820, 560)]),
+            (Relation::RightOf, [(2660, 200), (2660, 380), (2660, 560)]),
+            (Relation::Above, [(100, -880), (420, -880), (740, -880)]),
+            (Relation::Below, [(100, 1640), (420, 1640), (740, 1640)]),
+            (Relation::SameAs, [(100, 200), (420, 380), (740, 560)]),
+        ];
+        for (relation, positions) in expected {
+            for (align, position) in Align::ALL.into_iter().zip(positions) {
+                assert_eq!(resolve(relation, align, (1920, 1080), (100, 200), (2560, 1440)), position, "{} {}", relation.label(), align.label(relation));
+            }
+        }
+        // Bigger than the anchor hangs over both ends when centered, odd leftovers round toward the start
+        assert_eq!(resolve(Relation::Below, Align::Center, (2560, 1440), (0, 0), (1920, 1080)), (-320, 1080));
+        assert_eq!(resolve(Relation::RightOf, Align::Center, (1920, 1079), (0, 0), (1920, 1080)), (1920, 0));
+    }
+
+    fn monitor(id: &str, resolution: (u32, u32), position: (i32, i32)) -> Monitor {
+        Monitor {
+            id: id.to_string(),
+            proposed_status: true,
+            proposed_resolution: Some(resolution),
+            proposed_position: position,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn place_uses_the_proposed_size() {
+        let mut monitors = vec![monitor("eDP-1", (1920, 1080), (0, 0)), monitor("DP-1", (2560, 1440), (5000, 5000))];
+        // Turned on its side it's 1440 wide
+        monitors[1].proposed_orientation = String::from("left");
+        assert!(place(&mut monitors, 1, Relation::LeftOf, Align::End, 0).is_ok());
+        assert_eq!(monitors[1].proposed_position, (-1440, 1080 - 2560));
+        assert_eq!(monitors[0].proposed_position, (0, 0));
+    }
+
+    #[test]
+    fn place_against_itself() {
+        let mut monitors = vec![monitor("eDP-1", (1920, 1080), (0, 0))];
+        assert_eq!(place(&mut monitors, 0, Relation::RightOf, Align::Start, 0), Err(String::from("eDP-1 can't be placed relative to itself")));
+        assert_eq!(monitors[0].proposed_position, (0, 0));
+    }
+
+    #[test]
+    fn placing_back_and_forth() {
+        // Placement is one move against where the anchor is now, not a constraint that's kept. So A right of B and then
+        // B right of A can't chase each other, the second just moves B
+        let mut monitors = vec![monitor("A", (1920, 1080), (0, 0)), monitor("B", (1280, 1024), (0, 0))];
+        place(&mut monitors, 0, Relation::RightOf, Align::Start, 1).unwrap();
+        assert_eq!(monitors[0].proposed_position, (1280, 0));
+        place(&mut monitors, 1, Relation::RightOf, Align::Start, 0).unwrap();
+        assert_eq!(monitors[1].proposed_position, (3200, 0));
+        assert_eq!(monitors[0].proposed_position, (1280, 0));
+    }
+}
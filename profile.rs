@@ -0,0 +1,221 @@
+use crate::xrandr::EdidIdentity;
+use crate::{config, vrr, Monitor};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// Bump when the document changes in a way older brandr can't read. Documented in the README
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AvailableMode {
+    pub width: u32,
+    pub height: u32,
+    pub rates: Vec<f32>,
+}
+
+// One output in a layout. vrr_capable, modes and edid are informational, applying ignores them
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OutputLayout {
+    pub id: String,
+    pub enabled: bool,
+    pub mode: Option<Mode>,
+    pub rate: Option<f32>,
+    pub position: Position,
+    pub rotation: String,
+    pub primary: bool,
+    #[serde(default = "neutral_brightness")]
+    pub brightness: f32,
+    #[serde(default = "neutral_gamma")]
+    pub gamma: [f32; 3], // Red, green, blue
+    #[serde(default)]
+    pub vrr: Option<bool>, // Only for outputs whose driver can switch variable refresh per output
+    #[serde(default)]
+    pub vrr_capable: bool,
+    #[serde(default)]
+    pub modes: Vec<AvailableMode>,
+    #[serde(default)]
+    pub edid: Option<EdidIdentity>,
+}
+
+// Older layouts don't have brightness or gamma, those mean leave the screen alone
+fn neutral_brightness() -> f32 {
+    1.0
+}
+
+fn neutral_gamma() -> [f32; 3] {
+    [1.0; 3]
+}
+
+// A whole display setup. Same document for saved profiles, `brandr query --json` and `brandr apply --json`
+#[derive(Serialize, Deserialize)]
+pub struct Layout {
+    pub version: u32,
+    pub outputs: Vec<OutputLayout>,
+}
+
+impl Layout {
+    // Snapshot of what the monitors are set to (or will be once applied)
+    pub fn from_monitors(monitors: &[Monitor]) -> Layout {
+        Layout {
+            version: SCHEMA_VERSION,
+            outputs: monitors
+                .iter()
+                .map(|monitor| OutputLayout {
+                    id: monitor.id.clone(),
+                    enabled: monitor.proposed_status,
+                    mode: monitor.proposed_resolution.map(|(width, height)| Mode { width, height }),
+                    rate: monitor.proposed_rate,
+                    position: Position { x: monitor.proposed_position.0, y: monitor.proposed_position.1 },
+                    rotation: monitor.proposed_orientation.clone(),
+                    primary: monitor.proposed_primary,
+                    brightness: monitor.proposed_brightness,
+                    gamma: monitor.proposed_gamma,
+                    vrr: vrr::control(&monitor.proposed_properties).map(|index| vrr::is_on(&monitor.proposed_properties[index])),
+                    vrr_capable: vrr::capable(&monitor.properties),
+                    modes: monitor
+                        .modes
+                        .iter()
+                        .map(|((width, height), rates)| AvailableMode { width: *width, height: *height, rates: rates.clone() })
+                        .collect(),
+                    edid: monitor.edid.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    // Load the layout into the proposed settings. Connected outputs the layout doesn't mention get turned off like arandr does.
+    // Gives back the outputs in the layout that aren't connected right now
+    pub fn apply_to(&self, monitors: &mut [Monitor]) -> Vec<String> {
+        for monitor in monitors.iter_mut() {
+            match self.outputs.iter().find(|output| output.id == monitor.id) {
+                Some(output) => {
+                    monitor.proposed_status = output.enabled;
+                    if let Some(mode) = output.mode {
+                        monitor.proposed_resolution = Some((mode.width, mode.height));
+                    }
+                    monitor.proposed_rate = output.rate;
+                    monitor.proposed_position = (output.position.x, output.position.y);
+                    monitor.proposed_orientation = output.rotation.clone();
+                    monitor.proposed_primary = output.primary;
+                    monitor.proposed_brightness = output.brightness;
+                    monitor.proposed_gamma = output.gamma;
+                    if let (Some(on), Some(index)) = (output.vrr, vrr::control(&monitor.proposed_properties)) {
+                        vrr::switch(&mut monitor.proposed_properties[index], on);
+                    }
+                }
+                None => monitor.proposed_status = false,
+            }
+        }
+        self.outputs
+            .iter()
+            .filter(|output| !monitors.iter().any(|monitor| monitor.id == output.id))
+            .map(|output| output.id.clone())
+            .collect()
+    }
+}
+
+// Names are file names in the profiles dir, so nothing that could point somewhere else
+pub fn profile_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        return Err(format!("\"{}\" can't be a profile name. Use one without / or ..", name));
+    }
+    Ok(config::config_dir().join("profiles").join(format!("{}.json", name)))
+}
+
+pub fn save_profile(name: &str, layout: &Layout) -> Result<PathBuf, String> {
+    let path = profile_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(layout).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn load_profile(name: &str) -> Result<Layout, String> {
+    load_layout_file(&profile_path(name)?)
+}
+
+// Any layout document. "-" reads stdin so query output can be piped straight back in
+pub fn load_layout_file(path: &Path) -> Result<Layout, String> {
+    let contents = if path == Path::new("-") {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents).map_err(|e| format!("Couldn't read stdin: {}", e))?;
+        contents
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?
+    };
+    parse_layout(&contents).map_err(|e| format!("Bad layout {}: {}", path.display(), e))
+}
+
+pub fn parse_layout(contents: &str) -> Result<Layout, String> {
+    let layout: Layout = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    if layout.version > SCHEMA_VERSION {
+        return Err(format!("version {} is newer than this brandr understands ({})", layout.version, SCHEMA_VERSION));
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"{
+  "version": 1,
+  "outputs": [
+    {
+      "id": "DP-1",
+      "enabled": true,
+      "mode": { "width": 2560, "height": 1440 },
+      "rate": 143.97,
+      "position": { "x": -2560, "y": 0 },
+      "rotation": "normal",
+      "primary": true
+    },
+    { "id": "HDMI-1", "enabled": false, "mode": null, "rate": null, "position": { "x": 0, "y": 0 }, "rotation": "left", "primary": false }
+  ]
+}"#;
+
+    #[test]
+    fn loads_a_layout() {
+        let layout = parse_layout(LAYOUT).unwrap();
+        assert_eq!(layout.version, 1);
+        let output = &layout.outputs[0];
+        assert!(output.mode == Some(Mode { width: 2560, height: 1440 }));
+        assert!(output.position == Position { x: -2560, y: 0 });
+        // Left out, so they leave the screen alone
+        assert_eq!(output.brightness, 1.0);
+        assert_eq!(output.gamma, [1.0; 3]);
+        assert!(output.vrr.is_none() && output.edid.is_none());
+        assert!(layout.outputs[1].mode.is_none());
+    }
+
+    #[test]
+    fn round_trips() {
+        let layout = parse_layout(LAYOUT).unwrap();
+        let again = parse_layout(&serde_json::to_string(&layout).unwrap()).unwrap();
+        assert_eq!(again.outputs.len(), 2);
+        assert!(again.outputs[0].position == layout.outputs[0].position);
+    }
+
+    #[test]
+    fn refuses_other_documents() {
+        let newer = LAYOUT.replace("\"version\": 1", "\"version\": 2");
+        assert_eq!(parse_layout(&newer).err(), Some(String::from("version 2 is newer than this brandr understands (1)")));
+        assert!(parse_layout(&LAYOUT.replace("\"version\": 1,", "")).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::apply::run_xrandr;
+use std::process::Command;
+use std::str;
+
+// RandR providers, one per GPU. On hybrid laptops the external ports often hang off the second GPU and stay invisible
+// until that GPU is told to show what the first one renders (--setprovideroutputsource)
+
+pub const SOURCE_OUTPUT: &str = "Source Output"; // Can render for another provider's outputs
+pub const SINK_OUTPUT: &str = "Sink Output"; // Has outputs that can show another provider's rendering
+pub const SOURCE_OFFLOAD: &str = "Source Offload"; // Can render apps for another provider (PRIME offload)
+pub const SINK_OFFLOAD: &str = "Sink Offload"; // Can show apps another provider rendered
+
+#[derive(Clone)]
+pub struct Provider {
+    pub id: String, // Hex like 0x47. Names aren't unique, two GPUs can both be "modesetting"
+    pub name: String,
+    pub capabilities: Vec<String>,
+    pub crtcs: u32,
+    pub outputs: u32,
+    pub associated: u32, // How many other providers it's linked with. xrandr doesn't say which
+}
+
+impl Provider {
+    pub fn can(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|own| own == capability)
+    }
+
+    // "NVIDIA-G0 (0x104)" so providers with the same name can be told apart
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.name, self.id)
+    }
+}
+
+pub fn get_providers() -> Vec<Provider> {
+    let output = match Command::new("xrandr").arg("--listproviders").output() {
+        Ok(output) => output,
+        Err(_) => return Vec::new(), // Not worth failing over, the rest works on one GPU
+    };
+    parse_providers(str::from_utf8(&output.stdout).unwrap_or_default())
+}
+
+// "Provider 0: id: 0x47 cap: 0xf, Source Output, Sink Output, Source Offload, Sink Offload crtcs: 3 outputs: 1 associated providers: 1 name:modesetting"
+pub fn parse_providers(text: &str) -> Vec<Provider> {
+    text.lines()
+        .filter(|line| line.starts_with("Provider "))
+        .filter_map(|line| {
+            let between = |start: &str, end: &str| -> Option<String> {
+                let rest = &line[line.find(start)? + start.len()..];
+                Some(rest[..rest.find(end)?].trim().to_string())
+            };
+            let capabilities = between("cap: ", " crtcs:")?
+                .split(',')
+                .skip(1) // The bitmask itself
+                .map(|capability| capability.trim().to_string())
+                .collect();
+            Some(Provider {
+                id: between("id: ", " cap:")?,
+                name: line[line.find("name:")? + 5..].trim().to_string(),
+                capabilities,
+                crtcs: between("crtcs: ", " outputs:")?.parse().ok()?,
+                outputs: between("outputs: ", " associated")?.parse().ok()?,
+                associated: between("associated providers: ", " name:")?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+// Show what `source` renders on `provider`'s outputs. None unlinks them
+pub fn set_output_source(provider: &str, source: Option<&str>) -> Result<(), String> {
+    run_xrandr(&[String::from("--setprovideroutputsource"), provider.to_string(), source.unwrap_or("0x0").to_string()])
+}
+
+// Let `provider` render apps that get shown by `sink`. None unlinks them
+pub fn set_offload_sink(provider: &str, sink: Option<&str>) -> Result<(), String> {
+    run_xrandr(&[String::from("--setprovideroffloadsink"), provider.to_string(), sink.unwrap_or("0x0").to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_gpu() {
+        let text = "Providers: number : 1\n\
+                    Provider 0: id: 0x43 cap: 0x9, Source Output, Sink Offload crtcs: 4 outputs: 5 associated providers: 0 name:modesetting\n";
+        let providers = parse_providers(text);
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].label(), "modesetting (0x43)");
+        assert_eq!(providers[0].capabilities, [SOURCE_OUTPUT, SINK_OFFLOAD]);
+        assert_eq!((providers[0].crtcs, providers[0].outputs, providers[0].associated), (4, 5, 0));
+    }
+
+    #[test]
+    fn hybrid_laptop() {
+        // Intel driving the panel with the HDMI port on the NVIDIA card, same names as the modesetting driver gives both
+        let text = "Providers: number : 2\n\
+                    Provider 0: id: 0x47 cap: 0xf, Source Output, Sink Output, Source Offload, Sink Offload crtcs: 3 outputs: 1 associated providers: 1 name:modesetting\n\
+                    Provider 1: id: 0x104 cap: 0x2, Sink Output crtcs: 4 outputs: 4 associated providers: 1 name:modesetting\n";
+        let providers = parse_providers(text);
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[0].id, "0x47");
+        assert!(providers[0].can(SOURCE_OUTPUT) && providers[0].can(SINK_OFFLOAD));
+        assert_eq!(providers[1].id, "0x104");
+        assert_eq!(providers[1].name, "modesetting");
+        assert!(providers[1].can(SINK_OUTPUT) && !providers[1].can(SOURCE_OUTPUT));
+        assert_eq!((providers[1].crtcs, providers[1].outputs, providers[1].associated), (4, 4, 1));
+
+        // NVIDIA's own driver next to Intel's. Its card can only render for the other one's outputs
+        let text = "Providers: number : 2\n\
+                    Provider 0: id: 0x1b8 cap: 0x1, Source Output crtcs: 4 outputs: 8 associated providers: 1 name:NVIDIA-0\n\
+                    Provider 1: id: 0x1f1 cap: 0xf, Source Output, Sink Output, Source Offload, Sink Offload crtcs: 3 outputs: 5 associated providers: 1 name:Intel\n";
+        let providers = parse_providers(text);
+        assert_eq!(providers.iter().map(Provider::label).collect::<Vec<_>>(), ["NVIDIA-0 (0x1b8)", "Intel (0x1f1)"]);
+        assert_eq!(providers[0].capabilities, [SOURCE_OUTPUT]);
+        assert_eq!(providers[1].capabilities.len(), 4);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::Monitor;
+
+// Fold a fresh read of xrandr into the monitors the gui already has.
+// Current state always comes from the fresh read. Proposed edits and the preview carry over for monitors that are still
+// connected, and a monitor that moved to another connector is found again by its EDID.
+// Also gives back where each old monitor ended up so indices like the selection can follow
+pub fn merge(old: Vec<Monitor>, mut fresh: Vec<Monitor>) -> (Vec<Monitor>, Vec<Option<usize>>) {
+    let mut remap = vec![None; old.len()];
+    let mut old: Vec<Option<Monitor>> = old.into_iter().map(Some).collect();
+    let mut carried = vec![false; fresh.len()];
+
+    // Same connector first so identical monitors with identical EDIDs don't swap, then EDID alone for ones that moved
+    let passes: [fn(&Monitor, &Monitor) -> bool; 2] = [same_connector, same_edid];
+    for same in passes {
+        for (new_idx, monitor) in fresh.iter_mut().enumerate() {
+            if remap.contains(&Some(new_idx)) {
+                continue;
+            }
+            let found = old.iter().position(|previous| previous.as_ref().map_or(false, |previous| same(previous, monitor)));
+            if let Some(old_idx) = found {
+                let previous = old[old_idx].take().expect("only Some is matched");
+                carried[new_idx] = carry_over(previous, monitor);
+                remap[old_idx] = Some(new_idx);
+            }
+        }
+    }
+
+    // A kept edit made something primary. Don't end up with that and whatever xrandr says is primary now
+    if let Some(primary) = (0..fresh.len()).find(|&idx| carried[idx] && fresh[idx].proposed_primary) {
+        for (idx, monitor) in fresh.iter_mut().enumerate() {
+            monitor.proposed_primary = idx == primary;
+        }
+    }
+
+    (fresh, remap)
+}
+
+fn same_connector(previous: &Monitor, fresh: &Monitor) -> bool {
+    // A different EDID on the same connector means the monitor was swapped for another one
+    previous.id == fresh.id && (previous.edid.is_none() || fresh.edid.is_none() || previous.edid == fresh.edid)
+}
+
+fn same_edid(previous: &Monitor, fresh: &Monitor) -> bool {
+    fresh.edid.is_some() && previous.edid == fresh.edid
+}
+
+// Keep the preview and any proposed changes. Returns if edits were kept
+fn carry_over(previous: Monitor, fresh: &mut Monitor) -> bool {
+    let edited = previous.has_changes();
+    if previous.enabled == fresh.enabled { // Otherwise it went on or off and the old preview is wrong
+        fresh.texture = previous.texture;
+        fresh.texture_privacy = previous.texture_privacy;
+        fresh.last_screenshot_time = previous.last_screenshot_time;
+    }
+    fresh.ddc = previous.ddc; // Same monitor so the same bus
+
+    // Custom modes still waiting for Apply. Back on the fresh read so the kept edits below can find their mode
+    for modeline in previous.new_modes {
+        fresh.add_custom_mode(modeline);
+    }
+
+    if !edited {
+        return false; // Nothing proposed, just follow whatever changed outside brandr
+    }
+    // Mode might be gone if the monitor on the other end changed
+    let mode_still_there = previous.proposed_resolution.map_or(true, |mode| {
+        fresh.modes.is_empty() || fresh.modes.iter().any(|(size, _)| *size == mode)
+    });
+    if !mode_still_there {
+        return false;
+    }
+    fresh.proposed_status = previous.proposed_status;
+    fresh.proposed_resolution = previous.proposed_resolution;
+    fresh.proposed_rate = previous.proposed_rate;
+    fresh.proposed_position = previous.proposed_position;
+    fresh.proposed_orientation = previous.proposed_orientation;
+    fresh.proposed_primary = previous.proposed_primary;
+    fresh.proposed_virtual = previous.proposed_virtual;
+    fresh.proposed_brightness = previous.proposed_brightness;
+    fresh.proposed_gamma = previous.proposed_gamma;
+    fresh.proposed_panning = previous.proposed_panning;
+    // Only the values that were edited, by name. The rest follow the fresh read
+    for (current, proposed) in previous.properties.iter().zip(&previous.proposed_properties) {
+        if current.value == proposed.value {
+            continue;
+        }
+        if let Some(fresh_proposed) = fresh.proposed_properties.iter_mut().find(|property| property.name == proposed.name) {
+            fresh_proposed.value = proposed.value.clone();
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xrandr::EdidIdentity;
+
+    fn edid(serial: u32) -> Option<EdidIdentity> {
+        Some(EdidIdentity { manufacturer: String::from("DEL"), product: 0xa0c4, serial, name: Some(String::from("DELL U2720Q")), serial_string: None })
+    }
+
+    // Like a fresh read, proposed is what's on screen
+    fn monitor(id: &str, edid: Option<EdidIdentity>, position: (i32, i32)) -> Monitor {
+        Monitor {
+            id: id.to_string(),
+            edid,
+            proposed_status: true,
+            modes: vec![((1920, 1080), vec![60.0]), ((1280, 720), vec![60.0])],
+            current_position: position,
+            proposed_position: position,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renamed_connector_keeps_edits() {
+        // Docks and MST hubs hand out new connector names on replug
+        let mut edited = monitor("DP-1", edid(1), (0, 0));
+        edited.proposed_position = (1920, 0);
+        edited.proposed_resolution = Some((1280, 720));
+        let (merged, remap) = merge(vec![edited], vec![monitor("DP-3", edid(1), (0, 0))]);
+        assert_eq!(remap, [Some(0)]);
+        assert_eq!(merged[0].id, "DP-3");
+        assert_eq!(merged[0].proposed_position, (1920, 0));
+        assert_eq!(merged[0].proposed_resolution, Some((1280, 720)));
+    }
+
+    #[test]
+    fn identical_monitors_stay_on_their_connectors() {
+        // Same model with serial 0 on both, only the connector tells them apart
+        let mut left = monitor("DP-1", edid(0), (0, 0));
+        left.proposed_position = (-1920, 0);
+        let right = monitor("DP-2", edid(0), (1920, 0));
+        let (merged, remap) = merge(vec![left, right], vec![monitor("DP-2", edid(0), (1920, 0)), monitor("DP-1", edid(0), (0, 0))]);
+        assert_eq!(remap, [Some(1), Some(0)]);
+        assert_eq!(merged[1].proposed_position, (-1920, 0));
+        assert_eq!(merged[0].proposed_position, (1920, 0));
+    }
+
+    #[test]
+    fn edits_kept_and_outside_changes_followed() {
+        let mut edited = monitor("DP-1", edid(1), (0, 0));
+        edited.proposed_position = (0, 100);
+        edited.proposed_primary = true;
+        let untouched = monitor("HDMI-1", edid(2), (1920, 0));
+        // Something else moved HDMI-1 and made it primary in the meantime
+        let mut moved = monitor("HDMI-1", edid(2), (2560, 0));
+        moved.primary = true;
+        moved.proposed_primary = true;
+        let (merged, _) = merge(vec![edited, untouched], vec![monitor("DP-1", edid(1), (0, 0)), moved]);
+        assert_eq!(merged[0].proposed_position, (0, 100));
+        assert_eq!(merged[1].proposed_position, (2560, 0));
+        // The kept edit wins, but there's still just one primary
+        assert!(merged[0].proposed_primary);
+        assert!(!merged[1].proposed_primary);
+    }
+
+    #[test]
+    fn other_monitor_on_the_connector_drops_edits() {
+        let mut edited = monitor("DP-1", edid(1), (0, 0));
+        edited.proposed_position = (1920, 0);
+        let (merged, remap) = merge(vec![edited], vec![monitor("DP-1", edid(2), (0, 0))]);
+        assert_eq!(remap, [None]);
+        assert_eq!(merged[0].proposed_position, (0, 0));
+    }
+
+    #[test]
+    fn edits_dropped_when_the_mode_is_gone() {
+        let mut edited = monitor("DP-1", edid(1), (0, 0));
+        edited.proposed_resolution = Some((1280, 720));
+        edited.proposed_position = (1920, 0);
+        let mut fresh = monitor("DP-1", edid(1), (0, 0));
+        fresh.modes.retain(|(size, _)| *size != (1280, 720));
+        let (merged, remap) = merge(vec![edited], vec![fresh]);
+        assert_eq!(remap, [Some(0)]);
+        assert_eq!(merged[0].proposed_resolution, Some((1920, 1080)));
+        assert_eq!(merged[0].proposed_position, (0, 0));
+    }
+}
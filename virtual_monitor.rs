@@ -0,0 +1,182 @@
+use crate::cap::OutputGeometry;
+
+// A piece of an output the window manager treats as its own monitor (xrandr --setmonitor).
+// Kept relative to the output's corner so it moves along when the output gets dragged around
+#[derive(Clone, PartialEq)]
+pub struct VirtualMonitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub width_mm: u32,
+    pub height_mm: u32,
+}
+
+const MIN_SIZE: i32 = 64; // Smallest a region can be dragged down to
+
+impl VirtualMonitor {
+    // "name w/mmwxh/mmh+x+y output" for --setmonitor, placed with the output at `origin`
+    pub fn setmonitor_args(&self, origin: (i32, i32), output: &str) -> Vec<String> {
+        vec![
+            String::from("--setmonitor"),
+            self.name.clone(),
+            format!(
+                "{}/{}x{}/{}+{}+{}",
+                self.width, self.width_mm, self.height, self.height_mm, origin.0 + self.x, origin.1 + self.y
+            ),
+            output.to_string(),
+        ]
+    }
+}
+
+// Virtual monitors sitting on this output according to --listmonitors. The ones X makes for each output are skipped
+pub fn from_listmonitors(geometry: &[OutputGeometry], output: &str, origin: (i32, i32)) -> Vec<VirtualMonitor> {
+    geometry
+        .iter()
+        .filter(|monitor| !monitor.automatic && monitor.outputs.iter().any(|covered| covered == output))
+        .map(|monitor| VirtualMonitor {
+            name: monitor.name.clone(),
+            x: monitor.x - origin.0,
+            y: monitor.y - origin.1,
+            width: monitor.width,
+            height: monitor.height,
+            width_mm: monitor.width_mm,
+            height_mm: monitor.height_mm,
+        })
+        .collect()
+}
+
+// Cut an output into `count` equal side by side columns, or stacked rows. Last one takes the leftover pixels
+pub fn split(output: &str, size: (u32, u32), size_mm: (u32, u32), count: u32, rows: bool) -> Vec<VirtualMonitor> {
+    let count = count.max(1);
+    let (length, length_mm) = if rows { (size.1, size_mm.1) } else { (size.0, size_mm.0) };
+    (0..count)
+        .map(|n| {
+            let start = length * n / count;
+            let end = length * (n + 1) / count;
+            let start_mm = length_mm * n / count;
+            let end_mm = length_mm * (n + 1) / count;
+            let name = format!("{}~{}", output, n + 1); // Can't clash with real output names like DP-1-1
+            if rows {
+                VirtualMonitor { name, x: 0, y: start as i32, width: size.0, height: end - start, width_mm: size_mm.0, height_mm: end_mm - start_mm }
+            } else {
+                VirtualMonitor { name, x: start as i32, y: 0, width: end - start, height: size.1, width_mm: end_mm - start_mm, height_mm: size_mm.1 }
+            }
+        })
+        .collect()
+}
+
+// Pairs of regions that share a whole edge, so the line between them can be dragged. (first, second, vertical line)
+pub fn dividers(regions: &[VirtualMonitor]) -> Vec<(usize, usize, bool)> {
+    let mut found = Vec::new();
+    for (a, first) in regions.iter().enumerate() {
+        for (b, second) in regions.iter().enumerate() {
+            if first.x + first.width as i32 == second.x && first.y == second.y && first.height == second.height {
+                found.push((a, b, true));
+            } else if first.y + first.height as i32 == second.y && first.x == second.x && first.width == second.width {
+                found.push((a, b, false));
+            }
+        }
+    }
+    found
+}
+
+// Move the line between two neighbours to `at` (relative to the output). Physical size is shared out in proportion
+pub fn move_divider(regions: &mut [VirtualMonitor], first: usize, second: usize, vertical: bool, at: i32) {
+    let (start, end) = if vertical {
+        (regions[first].x, regions[second].x + regions[second].width as i32)
+    } else {
+        (regions[first].y, regions[second].y + regions[second].height as i32)
+    };
+    if end - start < MIN_SIZE * 2 {
+        return;
+    }
+    let at = at.clamp(start + MIN_SIZE, end - MIN_SIZE);
+    let (total_mm, first_size) = if vertical {
+        (regions[first].width_mm + regions[second].width_mm, at - start)
+    } else {
+        (regions[first].height_mm + regions[second].height_mm, at - start)
+    };
+    let first_mm = (total_mm as i64 * first_size as i64 / (end - start) as i64) as u32;
+    if vertical {
+        regions[first].width = first_size as u32;
+        regions[first].width_mm = first_mm;
+        regions[second].x = at;
+        regions[second].width = (end - at) as u32;
+        regions[second].width_mm = total_mm - first_mm;
+    } else {
+        regions[first].height = first_size as u32;
+        regions[first].height_mm = first_mm;
+        regions[second].y = at;
+        regions[second].height = (end - at) as u32;
+        regions[second].height_mm = total_mm - first_mm;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (x, y, width, height, width_mm, height_mm)
+    fn areas(regions: &[VirtualMonitor]) -> Vec<(i32, i32, u32, u32, u32, u32)> {
+        regions.iter().map(|region| (region.x, region.y, region.width, region.height, region.width_mm, region.height_mm)).collect()
+    }
+
+    #[test]
+    fn split_columns_and_rows() {
+        // 2560 doesn't go into 3, the rounding lands on the last one
+        let columns = split("DP-1", (2560, 1440), (597, 336), 3, false);
+        assert_eq!(columns.iter().map(|region| region.name.as_str()).collect::<Vec<_>>(), ["DP-1~1", "DP-1~2", "DP-1~3"]);
+        assert_eq!(areas(&columns), [(0, 0, 853, 1440, 199, 336), (853, 0, 853, 1440, 199, 336), (1706, 0, 854, 1440, 199, 336)]);
+        assert_eq!(dividers(&columns), [(0, 1, true), (1, 2, true)]);
+
+        let rows = split("DP-1", (1920, 2160), (600, 680), 2, true);
+        assert_eq!(areas(&rows), [(0, 0, 1920, 1080, 600, 340), (0, 1080, 1920, 1080, 600, 340)]);
+        assert_eq!(dividers(&rows), [(0, 1, false)]);
+    }
+
+    #[test]
+    fn split_into_nothing() {
+        // 0 would leave nothing to show, so it's one region covering the whole output
+        let whole = split("HDMI-1", (1920, 1080), (527, 296), 0, false);
+        assert_eq!(areas(&whole), [(0, 0, 1920, 1080, 527, 296)]);
+        assert!(dividers(&whole).is_empty());
+    }
+
+    #[test]
+    fn move_divider_shares_out_the_size() {
+        let mut columns = split("DP-1", (2000, 1000), (500, 250), 2, false);
+        move_divider(&mut columns, 0, 1, true, 1500);
+        assert_eq!(areas(&columns), [(0, 0, 1500, 1000, 375, 250), (1500, 0, 500, 1000, 125, 250)]);
+
+        let mut rows = split("DP-1", (1000, 2000), (250, 500), 2, true);
+        move_divider(&mut rows, 0, 1, false, 400);
+        assert_eq!(areas(&rows), [(0, 0, 1000, 400, 250, 100), (0, 400, 1000, 1600, 250, 400)]);
+    }
+
+    #[test]
+    fn move_divider_clamps() {
+        // Neither side goes under 64 pixels, even dragged off the output
+        let mut columns = split("DP-1", (2000, 1000), (500, 250), 2, false);
+        move_divider(&mut columns, 0, 1, true, 5000);
+        assert_eq!(areas(&columns), [(0, 0, 1936, 1000, 484, 250), (1936, 0, 64, 1000, 16, 250)]);
+        move_divider(&mut columns, 0, 1, true, -300);
+        assert_eq!(areas(&columns), [(0, 0, 64, 1000, 16, 250), (64, 0, 1936, 1000, 484, 250)]);
+
+        // Only counts the pair, not the whole output
+        let mut thirds = split("DP-1", (3000, 1000), (600, 200), 3, false);
+        move_divider(&mut thirds, 1, 2, true, 0);
+        assert_eq!(areas(&thirds)[1..], [(1000, 0, 64, 1000, 12, 200), (1064, 0, 1936, 1000, 388, 200)]);
+        assert_eq!(areas(&thirds)[0], (0, 0, 1000, 1000, 200, 200));
+    }
+
+    #[test]
+    fn move_divider_too_small_to_move() {
+        // Under two minimum sizes between them there's nowhere to put the line
+        let mut columns = split("DP-1", (100, 100), (20, 20), 2, false);
+        let before = areas(&columns);
+        move_divider(&mut columns, 0, 1, true, 30);
+        assert_eq!(areas(&columns), before);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::xrandr::{OutputProperty, PropertyKind};
+use std::fs;
+use std::path::PathBuf;
+
+// Variable refresh (FreeSync, G-Sync Compatible) on X. The kernel says which outputs can do it (vrr_capable), the driver's
+// VariableRefresh option in xorg.conf decides if it gets used at all, and then it kicks in for fullscreen apps.
+// Switching it per output only works when the driver gives the output a property for it
+
+const ON_VALUES: [&str; 4] = ["on", "true", "enabled", "1"];
+const OFF_VALUES: [&str; 4] = ["off", "false", "disabled", "0"];
+
+pub fn capable(properties: &[OutputProperty]) -> bool {
+    properties.iter().any(|property| property.name == "vrr_capable" && property.value == "1")
+}
+
+// Index of the writable property that switches VRR for this output, if the driver has one
+pub fn control(properties: &[OutputProperty]) -> Option<usize> {
+    properties.iter().position(|property| {
+        let name = property.name.to_lowercase();
+        let switch = match &property.kind {
+            PropertyKind::Range(min, max) => *min == 0 && *max >= 1,
+            PropertyKind::Choice(values) => {
+                values.iter().any(|value| ON_VALUES.contains(&value.to_lowercase().as_str()))
+                    && values.iter().any(|value| OFF_VALUES.contains(&value.to_lowercase().as_str()))
+            }
+            PropertyKind::ReadOnly => false,
+        };
+        switch && name != "vrr_capable" && (name.contains("vrr") || name.contains("variable refresh") || name.contains("freesync"))
+    })
+}
+
+pub fn is_on(property: &OutputProperty) -> bool {
+    ON_VALUES.contains(&property.value.to_lowercase().as_str())
+}
+
+// Whatever this property calls on or off
+pub fn switch(property: &mut OutputProperty, on: bool) {
+    let wanted = if on { ON_VALUES } else { OFF_VALUES };
+    property.value = match &property.kind {
+        PropertyKind::Choice(values) => values
+            .iter()
+            .find(|value| wanted.contains(&value.to_lowercase().as_str()))
+            .cloned()
+            .unwrap_or_else(|| property.value.clone()),
+        _ => String::from(if on { "1" } else { "0" }),
+    };
+}
+
+// The driver's VariableRefresh option as the Xorg log reports it. (driver, enabled), None when the log doesn't say,
+// which is also what the NVIDIA driver does (G-Sync is set in nvidia-settings there)
+pub fn driver_state() -> Option<(String, bool)> {
+    let display = std::env::var("DISPLAY").unwrap_or_default();
+    let number = display.rsplit(':').next().and_then(|rest| rest.split('.').next()).unwrap_or("0").to_string();
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/share"),
+    };
+    // Rootless X logs to the user's data dir, X running as root to /var/log
+    let logs = [data_home.join(format!("xorg/Xorg.{}.log", number)), PathBuf::from(format!("/var/log/Xorg.{}.log", number))];
+    let log = logs.iter().find_map(|path| fs::read_to_string(path).ok())?;
+
+    // "[    27.181] (II) AMDGPU(0): VariableRefresh: enabled"
+    log.lines().rev().find_map(|line| {
+        let (before, state) = line.split_once("VariableRefresh: ")?;
+        let driver = before.rsplit(") ").next()?.split('(').next()?.trim().to_string();
+        Some((driver, state.trim().starts_with("enabled")))
+    })
+}
@@ -0,0 +1,473 @@
+use crate::modeline::{self, Modeline};
+use crate::panning::Area;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Command;
+use std::str;
+
+// Stuff per output that only shows up in `xrandr --verbose`. The normal output is still what get_monitors_from_xrandr reads
+#[derive(Default)]
+pub struct VerboseOutput {
+    pub edid: Vec<u8>,
+    pub brightness: Option<f32>, // Software brightness. Only there for outputs with a CRTC
+    pub gamma: Option<[f32; 3]>, // Red, green, blue the way --gamma takes them
+    pub properties: Vec<OutputProperty>, // RandR output properties. Broadcast RGB, audio, max bpc...
+    pub panning: Option<Area>, // 0x0 when it isn't panning
+    pub tracking: Option<Area>,
+    pub modes: Vec<Modeline>, // Every mode on the output with its timings
+}
+
+// "Screen 0: minimum 320 x 200, current 3840 x 1080, maximum 16384 x 16384". Sizes the whole X screen can be
+#[derive(Clone, Copy, PartialEq)]
+pub struct ScreenLimits {
+    pub minimum: (u32, u32),
+    pub current: (u32, u32),
+    pub maximum: (u32, u32),
+}
+
+pub fn parse_screen_line(line: &str) -> Option<ScreenLimits> {
+    let size = |label: &str| -> Option<(u32, u32)> {
+        let rest = &line[line.find(label)? + label.len()..];
+        let (width, height) = rest.split(',').next()?.split_once(" x ")?;
+        Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+    };
+    Some(ScreenLimits { minimum: size("minimum ")?, current: size("current ")?, maximum: size("maximum ")? })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Connection {
+    Connected,
+    Disconnected,
+    Unknown, // Some drivers can't tell, VGA without load detection mostly
+}
+
+impl Connection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Connection::Connected => "connected",
+            Connection::Disconnected => "disconnected",
+            Connection::Unknown => "unknown",
+        }
+    }
+}
+
+// Every connector on the card, plugged in or not. get_monitors_from_xrandr only keeps the connected ones
+#[derive(Clone)]
+pub struct Connector {
+    pub id: String,
+    pub connection: Connection,
+    pub crtc: Option<Area>, // Where it's showing on the X screen, None when it's off
+}
+
+impl Connector {
+    // Unplugged but still driving a CRTC. Happens when a monitor gets pulled without anything turning its output off,
+    // and it keeps the X screen as big as it was. Unknown ones are left out, those are usually VGA or virtual outputs
+    // with a monitor really on them
+    pub fn is_stale(&self) -> bool {
+        self.connection == Connection::Disconnected && self.crtc.is_some()
+    }
+}
+
+pub fn get_connectors() -> Vec<Connector> {
+    match Command::new("xrandr").output() {
+        Ok(output) => parse_connectors(str::from_utf8(&output.stdout).unwrap_or_default()),
+        Err(_) => Vec::new(),
+    }
+}
+
+// "DP-2 disconnected 1920x1080+2560+0 (normal left inverted right x axis y axis) 0mm x 0mm"
+pub fn parse_connectors(text: &str) -> Vec<Connector> {
+    text.lines()
+        .filter(|line| !line.starts_with(char::is_whitespace) && !line.starts_with("Screen "))
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let connection = match *parts.get(1)? {
+                "connected" => Connection::Connected,
+                "disconnected" => Connection::Disconnected,
+                "unknown" => Connection::Unknown, // "unknown connection"
+                _ => return None,
+            };
+            Some(Connector { id: parts[0].to_string(), connection, crtc: parts.iter().find_map(|part| Area::parse(part)) })
+        })
+        .collect()
+}
+
+// One RandR output property and what it can be set to with --set
+#[derive(Clone, PartialEq)]
+pub struct OutputProperty {
+    pub name: String,
+    pub value: String,
+    pub kind: PropertyKind,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum PropertyKind {
+    Choice(Vec<String>), // "supported:" list
+    Range(i64, i64), // "range: (min, max)"
+    ReadOnly, // Blobs and anything without a list or range. Nothing sane to set them to
+}
+
+// Lines one tab in that are about the output or its CRTC rather than properties
+const NOT_PROPERTIES: [&str; 14] = [
+    "EDID", "Identifier", "Timestamp", "Subpixel", "Gamma", "Brightness", "Clones", "CRTC", "CRTCs", "Panning", "Tracking",
+    "Border", "Transform", "filter",
+];
+// The kernel won't take new values for these even though xrandr lists them like the rest
+const IMMUTABLE_PROPERTIES: [&str; 5] = ["CONNECTOR_ID", "non-desktop", "vrr_capable", "PATH", "TILE"];
+
+// xrandr prints each supported value as a space and then the value padded out to 12 characters. Values can have spaces
+// in them ("Full aspect", "Limited 16:235") so go by the columns, and past 12 until the next space for long ones
+fn parse_supported(list: &str) -> Vec<String> {
+    let chars: Vec<char> = list.chars().collect();
+    let mut values = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        start += 1; // The space in front
+        let mut end = (start + 12).min(chars.len());
+        while end < chars.len() && chars[end] != ' ' {
+            end += 1;
+        }
+        let value: String = chars[start.min(end)..end].iter().collect();
+        if !value.trim().is_empty() {
+            values.push(value.trim().to_string());
+        }
+        start = end;
+    }
+    values
+}
+
+// "range: (0, 128)"
+fn parse_range(range: &str) -> Option<(i64, i64)> {
+    let (min, max) = range.trim().strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+    Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+}
+
+// Who the monitor says it is. Same monitor gives the same identity on any connector so it's what saved settings key on
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdidIdentity {
+    pub manufacturer: String, // Three letter PNP id. DEL, SAM, GSM...
+    pub product: u16,
+    pub serial: u32,
+    pub name: Option<String>, // "DELL U2720Q"
+    pub serial_string: Option<String>, // Lots of monitors leave the number at 0 and put the real serial here
+}
+
+impl VerboseOutput {
+    pub fn edid_identity(&self) -> Option<EdidIdentity> {
+        let edid = &self.edid;
+        if edid.len() < 128 || edid[0..8] != [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00] {
+            return None;
+        }
+        // Manufacturer is three 5 bit letters packed big endian, 1 = A
+        let packed = u16::from_be_bytes([edid[8], edid[9]]);
+        let manufacturer: String = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'@' + ((packed >> shift) & 0x1F) as u8) as char)
+            .collect();
+
+        Some(EdidIdentity {
+            manufacturer,
+            product: u16::from_le_bytes([edid[10], edid[11]]),
+            serial: u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]),
+            name: self.edid_descriptor(0xFC),
+            serial_string: self.edid_descriptor(0xFF),
+        })
+    }
+
+    // Text out of one of the four 18 byte descriptors. 0xFC is the monitor name, 0xFF the serial
+    fn edid_descriptor(&self, tag: u8) -> Option<String> {
+        for descriptor in self.edid[54..126].chunks(18) {
+            // Display descriptors start with 0 0 0 then the tag
+            if descriptor[0] == 0 && descriptor[1] == 0 && descriptor[2] == 0 && descriptor[3] == tag {
+                let text: String = descriptor[5..18].iter()
+                    .take_while(|&&b| b != b'\n')
+                    .map(|&b| b as char)
+                    .collect();
+                let text = text.trim().to_string();
+                if !text.is_empty() {
+                    return Some(text);
+                }
+            }
+        }
+        None
+    }
+}
+
+// Name, pixel clock and sync polarity of a mode. The rest comes from the h: and v: lines after it
+fn parse_mode_header(line: &str) -> Option<Modeline> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let megahertz: f64 = words.get(2)?.strip_suffix("MHz")?.parse().ok()?;
+    let (width, height) = modeline::size_from_name(words[0]).unwrap_or((0, 0)); // The h: and v: lines fill it in anyway
+    Some(Modeline {
+        name: words[0].to_string(),
+        clock_khz: (megahertz * 1000.0).round() as u32,
+        hdisplay: width,
+        hsync_start: 0,
+        hsync_end: 0,
+        htotal: 0,
+        vdisplay: height,
+        vsync_start: 0,
+        vsync_end: 0,
+        vtotal: 0,
+        hsync_positive: words.contains(&"+HSync"),
+        vsync_positive: words.contains(&"+VSync"),
+    })
+}
+
+// Number after `key` on an h: or v: line
+fn timing_field(words: &[&str], key: &str) -> Option<u32> {
+    words.iter().position(|word| *word == key).and_then(|index| words.get(index + 1)?.parse().ok())
+}
+
+pub fn get_verbose_outputs() -> Result<HashMap<String, VerboseOutput>, String> {
+    let output = Command::new("xrandr")
+        .arg("--verbose")
+        .output()
+        .map_err(|e| format!("Failed to run xrandr: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("xrandr --verbose failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(parse_verbose(&String::from_utf8_lossy(&output.stdout)))
+}
+
+pub fn parse_verbose(output_str: &str) -> HashMap<String, VerboseOutput> {
+    let mut outputs: HashMap<String, VerboseOutput> = HashMap::new();
+    let mut current: Option<String> = None; // Output we're reading properties for
+    let mut in_edid = false;
+
+    for line in output_str.lines() {
+        // Output lines are the only ones not indented. "DP-1 connected primary 2560x1440+0+0 ..."
+        if !line.starts_with(char::is_whitespace) {
+            in_edid = false;
+            current = if line.starts_with("Screen ") {
+                None
+            } else {
+                line.split_whitespace().next().map(|id| id.to_string())
+            };
+            if let Some(id) = &current {
+                outputs.entry(id.clone()).or_default();
+            }
+            continue;
+        }
+        let Some(id) = &current else { continue };
+        let info = outputs.get_mut(id).expect("output added above");
+
+        // Properties are one tab in, their values (EDID hex, supported lists) two tabs in
+        if line.starts_with("\t\t") {
+            if in_edid {
+                let hex = line.trim();
+                for i in (0..hex.len().saturating_sub(1)).step_by(2) {
+                    if let Ok(byte) = u8::from_str_radix(&hex[i..i + 2], 16) {
+                        info.edid.push(byte);
+                    }
+                }
+            } else if let Some(property) = info.properties.last_mut() {
+                let detail = &line[2..];
+                if let Some(list) = detail.strip_prefix("supported:") {
+                    if !IMMUTABLE_PROPERTIES.contains(&property.name.as_str()) {
+                        property.kind = PropertyKind::Choice(parse_supported(list));
+                    }
+                } else if let Some((min, max)) = detail.strip_prefix("range:").and_then(parse_range) {
+                    if !IMMUTABLE_PROPERTIES.contains(&property.name.as_str()) {
+                        property.kind = PropertyKind::Range(min, max);
+                    }
+                } else if let (Some(rest), PropertyKind::Choice(values)) = (detail.strip_prefix("          "), &mut property.kind) {
+                    // xrandr wraps the supported list after every 4 values and lines the rest up under the first
+                    values.extend(parse_supported(rest));
+                } else {
+                    property.kind = PropertyKind::ReadOnly; // Rest of a blob like CTM
+                }
+            }
+            continue;
+        }
+        let property = line.trim_start();
+        in_edid = property.starts_with("EDID:");
+        // "  1920x1080 (0x48) 148.500MHz +HSync +VSync *current +preferred" then its h: and v: lines further in
+        if line.starts_with("  ") && !line.starts_with("   ") {
+            if let Some(mode) = parse_mode_header(property) {
+                info.modes.push(mode);
+            }
+            continue;
+        }
+        if let (Some(timings), Some(mode)) = (property.strip_prefix("h:"), info.modes.last_mut()) {
+            // "h: width  1920 start 2008 end 2052 total 2200 skew    0 clock  67.50KHz"
+            let words: Vec<&str> = timings.split_whitespace().collect();
+            mode.hdisplay = timing_field(&words, "width").unwrap_or(mode.hdisplay);
+            mode.hsync_start = timing_field(&words, "start").unwrap_or(0);
+            mode.hsync_end = timing_field(&words, "end").unwrap_or(0);
+            mode.htotal = timing_field(&words, "total").unwrap_or(0);
+            continue;
+        }
+        if let (Some(timings), Some(mode)) = (property.strip_prefix("v:"), info.modes.last_mut()) {
+            let words: Vec<&str> = timings.split_whitespace().collect();
+            mode.vdisplay = timing_field(&words, "height").unwrap_or(mode.vdisplay);
+            mode.vsync_start = timing_field(&words, "start").unwrap_or(0);
+            mode.vsync_end = timing_field(&words, "end").unwrap_or(0);
+            mode.vtotal = timing_field(&words, "total").unwrap_or(0);
+            continue;
+        }
+        // "\tBroadcast RGB: Automatic". Mode lines are indented with spaces instead
+        if let (true, Some((name, value))) = (line.starts_with('\t'), property.split_once(':')) {
+            if !NOT_PROPERTIES.contains(&name) {
+                info.properties.push(OutputProperty { name: name.to_string(), value: value.trim().to_string(), kind: PropertyKind::ReadOnly });
+            }
+        }
+        if let Some(value) = property.strip_prefix("Panning:") {
+            info.panning = Area::parse(value);
+        }
+        if let Some(value) = property.strip_prefix("Tracking:") {
+            info.tracking = Area::parse(value);
+        }
+        if let Some(value) = property.strip_prefix("Brightness:") {
+            info.brightness = value.trim().parse().ok();
+        }
+        if let Some(value) = property.strip_prefix("Gamma:") {
+            // xrandr prints the ramp exponent, which is 1 over what --gamma takes. Flip it back so they round trip.
+            // Only printed to 2 digits so round off the noise
+            let channels: Vec<f32> = value.trim()
+                .split(':')
+                .filter_map(|channel| channel.parse::<f32>().ok())
+                .filter(|channel| *channel > 0.0)
+                .map(|channel| (100.0 / channel).round() / 100.0)
+                .collect();
+            if let [red, green, blue] = channels[..] {
+                info.gamma = Some([red, green, blue]);
+            }
+        }
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cut down from a real `xrandr --verbose` on an Intel card, whitespace and all
+    const VERBOSE: [&str; 31] = [
+        "Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384",
+        "DP-1 connected primary 2560x1440+0+0 (0x47) normal (normal left inverted right x axis y axis) 597mm x 336mm",
+        "\tIdentifier: 0x46 ",
+        "\tTimestamp:  1203885",
+        "\tSubpixel:   unknown",
+        "\tGamma:      1.0:1.0:1.0",
+        "\tBrightness: 1.0",
+        "\tClones:    ",
+        "\tCRTC:       0",
+        "\tCRTCs:      0 1 2",
+        "\tTransform:  1.000000 0.000000 0.000000",
+        "\t            0.000000 1.000000 0.000000",
+        "\t            0.000000 0.000000 1.000000",
+        "\t           filter: ",
+        "\tColorspace: Default ",
+        "\t\tsupported: Default      RGB_Wide_Gamut_Fixed_Point RGB_Wide_Gamut_Floating_Point opRGB       ",
+        "\t\t           DCI-P3_RGB_D65 DCI-P3_RGB_Theater BT2020_RGB  ",
+        "\tmax bpc: 12 ",
+        "\t\trange: (6, 12)",
+        "\tBroadcast RGB: Automatic ",
+        "\t\tsupported: Automatic    Full         Limited 16:235",
+        "\tCONNECTOR_ID: 95 ",
+        "\t\tsupported: 95          ",
+        "  2560x1440 (0x47) 241.500MHz +HSync -VSync *current +preferred",
+        "        h: width  2560 start 2608 end 2640 total 2720 skew    0 clock  88.79KHz",
+        "        v: height 1440 start 1443 end 1448 total 1481           clock  59.95Hz",
+        "  2560x1080R_75.00 (0x1c2) 229.750MHz +HSync -VSync",
+        "        h: width  2560 start 2608 end 2640 total 2720 skew    0 clock  84.47KHz",
+        "        v: height 1080 start 1083 end 1093 total 1126           clock  75.02Hz",
+        "DP-2 disconnected (normal left inverted right x axis y axis)",
+        "\tIdentifier: 0x4e ",
+    ];
+
+    fn property<'a>(info: &'a VerboseOutput, name: &str) -> &'a OutputProperty {
+        info.properties.iter().find(|property| property.name == name).unwrap()
+    }
+
+    #[test]
+    fn wrapped_supported_list() {
+        let outputs = parse_verbose(&VERBOSE.join("\n"));
+        let colorspace = property(&outputs["DP-1"], "Colorspace");
+        assert_eq!(colorspace.value, "Default");
+        let expected = [
+            "Default", "RGB_Wide_Gamut_Fixed_Point", "RGB_Wide_Gamut_Floating_Point", "opRGB",
+            "DCI-P3_RGB_D65", "DCI-P3_RGB_Theater", "BT2020_RGB",
+        ];
+        assert!(colorspace.kind == PropertyKind::Choice(expected.map(String::from).to_vec()));
+
+        // Spaces inside a value
+        let broadcast = property(&outputs["DP-1"], "Broadcast RGB");
+        assert!(broadcast.kind == PropertyKind::Choice(["Automatic", "Full", "Limited 16:235"].map(String::from).to_vec()));
+    }
+
+    #[test]
+    fn range_and_immutable_properties() {
+        let outputs = parse_verbose(&VERBOSE.join("\n"));
+        let bpc = property(&outputs["DP-1"], "max bpc");
+        assert_eq!(bpc.value, "12");
+        assert!(bpc.kind == PropertyKind::Range(6, 12));
+        assert!(property(&outputs["DP-1"], "CONNECTOR_ID").kind == PropertyKind::ReadOnly);
+        // The Transform matrix and filter aren't properties
+        assert_eq!(outputs["DP-1"].properties.len(), 4);
+        assert!(outputs["DP-2"].properties.is_empty());
+    }
+
+    #[test]
+    fn mode_timings() {
+        let outputs = parse_verbose(&VERBOSE.join("\n"));
+        let info = &outputs["DP-1"];
+        assert_eq!(info.brightness, Some(1.0));
+        assert_eq!(info.gamma, Some([1.0; 3]));
+        assert_eq!(info.modes.len(), 2);
+        let custom = &info.modes[1];
+        assert_eq!(custom.name, "2560x1080R_75.00");
+        assert_eq!(custom.clock_khz, 229_750);
+        assert_eq!((custom.hdisplay, custom.hsync_start, custom.hsync_end, custom.htotal), (2560, 2608, 2640, 2720));
+        assert_eq!((custom.vdisplay, custom.vsync_start, custom.vsync_end, custom.vtotal), (1080, 1083, 1093, 1126));
+        assert!(custom.hsync_positive && !custom.vsync_positive);
+    }
+
+    #[test]
+    fn stale_connectors() {
+        let text = [
+            "Screen 0: minimum 320 x 200, current 4480 x 1440, maximum 16384 x 16384",
+            "eDP-1 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 309mm x 174mm",
+            "   2560x1440     59.98*+",
+            "DP-1 disconnected (normal left inverted right x axis y axis)",
+            "DP-2 disconnected 1920x1080+2560+0 (normal left inverted right x axis y axis) 0mm x 0mm",
+            "  1920x1080 (0x48) 148.500MHz +HSync +VSync",
+            "VGA-1 unknown connection 1024x768+0+0 (normal left inverted right x axis y axis) 0mm x 0mm",
+            "HDMI-1 connected (normal left inverted right x axis y axis)",
+        ]
+        .join("\n");
+        let connectors = parse_connectors(&text);
+        let ids: Vec<&str> = connectors.iter().map(|connector| connector.id.as_str()).collect();
+        assert_eq!(ids, ["eDP-1", "DP-1", "DP-2", "VGA-1", "HDMI-1"]);
+
+        // Connected and on
+        assert!(connectors[0].connection == Connection::Connected);
+        assert!(connectors[0].crtc == Some(Area { width: 2560, height: 1440, x: 0, y: 0 }));
+        assert!(!connectors[0].is_stale());
+        // Unplugged and already off
+        assert!(connectors[1].connection == Connection::Disconnected);
+        assert!(connectors[1].crtc.is_none());
+        assert!(!connectors[1].is_stale());
+        // Unplugged but still holding its CRTC
+        assert!(connectors[2].connection == Connection::Disconnected);
+        assert!(connectors[2].crtc == Some(Area { width: 1920, height: 1080, x: 2560, y: 0 }));
+        assert!(connectors[2].is_stale());
+        // Can't tell, so left alone
+        assert!(connectors[3].connection == Connection::Unknown);
+        assert!(!connectors[3].is_stale());
+        // Connected but off
+        assert!(connectors[4].crtc.is_none());
+        assert!(!connectors[4].is_stale());
+    }
+
+    #[test]
+    fn screen_line() {
+        let screen = parse_screen_line("Screen 0: minimum 320 x 200, current 4480 x 1440, maximum 16384 x 16384").unwrap();
+        assert!(screen == ScreenLimits { minimum: (320, 200), current: (4480, 1440), maximum: (16384, 16384) });
+        // Older cards top out a lot lower
+        let screen = parse_screen_line("Screen 0: minimum 8 x 8, current 1024 x 768, maximum 2048 x 2048").unwrap();
+        assert_eq!(screen.maximum, (2048, 2048));
+        assert!(parse_screen_line("Screen 0: minimum 320 x 200, current 1920 x 1080").is_none());
+    }
+}